@@ -34,21 +34,25 @@ impl AABB {
 
         diff.as_ref()
             .iter()
-            .position(|&e| e == diff.min_element())
+            .position(|&e| e == diff.max_element())
             .unwrap()
     }
 
     /// Perform an intersection test with an AABB
+    ///
+    /// The box is hit when the ray overlaps it anywhere
+    /// between position_min and position_max.
+    ///
     /// Reference: https://medium.com/@bromanz/another-view-on-the-classic-ray
     /// -aabb-intersection-algorithm-for-bvh-traversal-41125138b525
-    pub fn hit(&self, ray: &Ray, _position_min: f32, _position_max: f32) -> bool {
+    pub fn hit(&self, ray: &Ray, position_min: f32, position_max: f32) -> bool {
         let t0 = (self.minimum - ray.origin) * ray.inverse_direction;
         let t1 = (self.maximum - ray.origin) * ray.inverse_direction;
 
-        let tmin = t0.min(t1);
-        let tmax = t1.max(t0);
+        let tmin = t0.min(t1).max_element().max(position_min);
+        let tmax = t1.max(t0).min_element().min(position_max);
 
-        tmin.max_element() <= tmax.min_element()
+        tmin <= tmax
     }
 
     /// Create an AABB that encapsulates two volumes
@@ -1,5 +1,6 @@
 use glam::Vec3;
 
+#[derive(Clone)]
 pub struct OrthonormalBasis {
     axis: Vec<Vec3>,
 }
@@ -25,6 +26,21 @@ impl OrthonormalBasis {
         OrthonormalBasis { axis: vec![u, v, w] }
     }
 
+    /// Create a frame around the normal whose u axis follows the tangent
+    ///
+    /// The tangent is made perpendicular to the normal. None is returned
+    /// when the tangent is parallel to the normal.
+    pub fn from_tangent(normal: &Vec3, tangent: &Vec3) -> Option<OrthonormalBasis> {
+        let w = normal.normalize();
+        let u = *tangent - w * w.dot(*tangent);
+        if u.length() < 1e-6 {
+            return None;
+        }
+
+        let u = u.normalize();
+        Some(OrthonormalBasis { axis: vec![u, w.cross(u), w] })
+    }
+
     pub fn u(&self) -> Vec3 {
         self.axis[0]
     }
@@ -40,6 +56,11 @@ impl OrthonormalBasis {
     pub fn local(&self, v: &Vec3) -> Vec3 {
         v.x() * self.u() + v.y() * self.v() + v.z() * self.w()
     }
+
+    /// Express a direction in the coordinates of the basis, undoing local
+    pub fn to_local(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(v.dot(self.u()), v.dot(self.v()), v.dot(self.w()))
+    }
 }
 
 #[cfg(test)]
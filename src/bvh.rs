@@ -1,118 +1,350 @@
-use std::cmp::Ordering;
 use std::sync::Arc;
 
+use glam::Vec3;
+
 use aabb::AABB;
 use hitable::{HitRecord, Hitable};
 use ray::Ray;
 
+/// The number of bins the centroids are sorted into along each axis
+const SAH_BINS: usize = 12;
+
+/// The cost of traversing a node relative to intersecting an object
+const TRAVERSAL_COST: f32 = 0.125;
+
+/// The deepest level of the hierarchy, below which the objects are kept in a leaf
+const MAX_DEPTH: usize = 64;
+
+/// SplitMethod chooses how the objects of a node are divided between its children
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SplitMethod {
+    /// Split at the median object along the longest axis of the node
+    Median,
+    /// Split where the surface area heuristic estimates the cheapest traversal
+    SAH,
+}
+
+/// LinearNode is a node of the BVH stored in a flat array in depth first order
+///
+/// An interior node is followed by its first child and stores the index
+/// of its second child. A leaf stores the range of its objects instead.
 #[derive(Clone)]
-pub struct BVH {
-    left: Arc<dyn Hitable>,
-    right: Arc<dyn Hitable>,
+struct LinearNode {
     bbox: AABB,
+    kind: NodeKind,
+}
+
+#[derive(Clone)]
+enum NodeKind {
+    Interior { second_child: usize, axis: usize },
+    Leaf { first_object: usize, count: usize },
+}
+
+#[derive(Clone)]
+pub struct BVH {
+    nodes: Vec<LinearNode>,
+    objects: Vec<Arc<dyn Hitable>>,
 }
 
 impl BVH {
     /// Construct a new BVH from the objects in the scene.
     ///
-    /// An axis is chosen by random and the objects in the scene
-    /// are sorted upon that axis. Then, child objects are created
-    /// until only leaf nodes exist.
-    pub fn new(mut world: &mut Vec<Arc<dyn Hitable>>, start_time: f32, end_time: f32) -> BVH {
-        let mut main_box = world[0].bounding_box(start_time, end_time).unwrap();
-
-        for i in 1..world.len() {
-            let new_box = world[i].bounding_box(start_time, end_time).unwrap();
-            main_box = main_box.surrounding_box(&new_box);
-        }
+    /// The objects are split with the surface area heuristic
+    /// and up to four objects are kept in each leaf.
+    pub fn new(world: &mut Vec<Arc<dyn Hitable>>, start_time: f32, end_time: f32) -> BVH {
+        BVH::with_split(world, start_time, end_time, SplitMethod::SAH, 4)
+    }
 
-        let axis = main_box.longest_axis();
+    /// Construct a new BVH that splits the objects with the given method
+    ///
+    /// Objects are split into child nodes until no more than leaf_size
+    /// objects are left. The surface area heuristic may also stop splitting
+    /// earlier when testing the objects is cheaper than splitting them.
+    pub fn with_split(world: &mut Vec<Arc<dyn Hitable>>,
+                      start_time: f32,
+                      end_time: f32,
+                      split: SplitMethod,
+                      leaf_size: usize)
+                      -> BVH {
+        let mut primitives: Vec<Primitive> =
+            world.iter()
+                 .map(|hitable| {
+                     let bbox = hitable.bounding_box(start_time, end_time).unwrap();
+                     let centroid = 0.5 * (bbox.minimum + bbox.maximum);
+                     Primitive { hitable: hitable.clone(),
+                                 bbox,
+                                 centroid }
+                 })
+                 .collect();
 
-        world.sort_by(|a, b| box_compare(a, b, axis, start_time, end_time));
+        let mut bvh = BVH { nodes: Vec::with_capacity(2 * primitives.len()),
+                            objects: Vec::with_capacity(primitives.len()) };
+        bvh.build(&mut primitives, split, leaf_size.max(1), 0);
+        bvh
+    }
 
-        let mut left = world[0].clone();
-        let mut right = world[0].clone();
+    /// Append the subtree that holds the given primitives and return its bounding box
+    fn build(&mut self,
+             primitives: &mut [Primitive],
+             split: SplitMethod,
+             leaf_size: usize,
+             depth: usize)
+             -> AABB {
+        let fits = primitives.len() <= leaf_size;
 
-        if world.len() == 2 {
-            left = world[0].clone();
-            right = world[1].clone();
-        } else if world.len() > 2 {
-            let mut right_objects = world.split_off(world.len() / 2);
-            left = Arc::new(BVH::new(&mut world, start_time, end_time));
-            right = Arc::new(BVH::new(&mut right_objects, start_time, end_time));
-        }
+        let division = if primitives.len() <= 1 || depth == MAX_DEPTH {
+            None
+        } else {
+            match split {
+                SplitMethod::Median if fits => None,
+                SplitMethod::Median => Some(median_split(primitives)),
+                SplitMethod::SAH => match sah_split(primitives) {
+                    Some((_, _, cost)) if fits && cost >= primitives.len() as f32 => None,
+                    Some((middle, axis, _)) => Some((middle, axis)),
+                    None if fits => None,
+                    // the centroids coincide so the objects can only be split by count
+                    None => Some(median_split(primitives)),
+                },
+            }
+        };
+
+        let index = self.nodes.len();
+
+        match division {
+            None => {
+                let bbox = primitives.iter().fold(AABB::new(), |bbox, primitive| {
+                                                bbox.surrounding_box(&primitive.bbox)
+                                            });
+                self.nodes.push(LinearNode { bbox: bbox.clone(),
+                                             kind: NodeKind::Leaf { first_object: self.objects.len(),
+                                                                    count: primitives.len() } });
+                self.objects.extend(primitives.iter().map(|p| p.hitable.clone()));
+                bbox
+            }
+            Some((middle, axis)) => {
+                self.nodes.push(LinearNode { bbox: AABB::new(),
+                                             kind: NodeKind::Interior { second_child: 0, axis } });
 
-        let bbox = left.bounding_box(start_time, end_time)
-                       .unwrap()
-                       .surrounding_box(&right.bounding_box(start_time, end_time).unwrap());
+                let (left, right) = primitives.split_at_mut(middle);
+                let left_box = self.build(left, split, leaf_size, depth + 1);
+                let second_child = self.nodes.len();
+                let right_box = self.build(right, split, leaf_size, depth + 1);
 
-        BVH { left, right, bbox }
+                let bbox = left_box.surrounding_box(&right_box);
+                self.nodes[index] = LinearNode { bbox: bbox.clone(),
+                                                 kind: NodeKind::Interior { second_child, axis } };
+                bbox
+            }
+        }
     }
 }
 
 impl Hitable for BVH {
-    /// Test whether the ray intersects the bounding volume.
+    /// Find the closest object that the ray intersects
     ///
-    /// We check for an intersection with a node in the BVH and
-    /// return the node that is hit. If both the left and right
-    /// child are hit, then we return the node closest to the ray.
+    /// The nodes are visited front to back with a stack. The nearer child
+    /// of an interior node, judged by the direction of the ray along the axis
+    /// the node was split on, is visited first. Every hit shrinks the interval
+    /// of the ray, so that nodes behind the closest hit so far are skipped.
     fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        if self.bbox.hit(&ray, t_min, t_max) {
-            let left = self.left.hit(&ray, t_min, t_max);
-            let right = self.right.hit(&ray, t_min, t_max);
-            match (left, right) {
-                (Some(left), Some(right)) => {
-                    if left.parameter < right.parameter {
-                        Some(left)
-                    } else {
-                        Some(right)
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut closest = None;
+        let mut t_max = t_max;
+
+        let mut stack = [0; MAX_DEPTH + 1];
+        let mut stack_size = 0;
+        let mut node_index = 0;
+
+        loop {
+            let node = &self.nodes[node_index];
+
+            if node.bbox.hit(ray, t_min, t_max) {
+                match node.kind {
+                    NodeKind::Leaf { first_object, count } => {
+                        for object in &self.objects[first_object..first_object + count] {
+                            if let Some(hit) = object.hit(ray, t_min, t_max) {
+                                t_max = hit.parameter;
+                                closest = Some(hit);
+                            }
+                        }
+                    }
+                    NodeKind::Interior { second_child, axis } => {
+                        // visit the child on the side the ray comes from first
+                        if ray.direction.as_ref()[axis] < 0.0 {
+                            stack[stack_size] = node_index + 1;
+                            node_index = second_child;
+                        } else {
+                            stack[stack_size] = second_child;
+                            node_index += 1;
+                        }
+                        stack_size += 1;
+                        continue;
                     }
                 }
-                (Some(left), None) => Some(left),
-                (None, Some(right)) => Some(right),
-                _ => None,
             }
-        } else {
-            None
+
+            if stack_size == 0 {
+                break;
+            }
+            stack_size -= 1;
+            node_index = stack[stack_size];
         }
+
+        closest
     }
 
     fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
-        Some(self.bbox.clone())
+        self.nodes.first().map(|node| node.bbox.clone())
     }
 }
 
-/// Compare the coordinates of two bounding volumes.
+/// An object with its bounding box over the time of the scene
+struct Primitive {
+    hitable: Arc<dyn Hitable>,
+    bbox: AABB,
+    centroid: Vec3,
+}
+
+/// Sort the primitives along the longest axis of their bounds and split them in half
+fn median_split(primitives: &mut [Primitive]) -> (usize, usize) {
+    let bounds = primitives.iter()
+                           .fold(AABB::new(), |bbox, primitive| bbox.surrounding_box(&primitive.bbox));
+    let axis = bounds.longest_axis();
+
+    primitives.sort_by(|a, b| {
+                  a.bbox.minimum.as_ref()[axis].partial_cmp(&b.bbox.minimum.as_ref()[axis])
+                                               .unwrap()
+              });
+
+    (primitives.len() / 2, axis)
+}
+
+/// Split the primitives where the surface area heuristic is lowest
+///
+/// The centroids are sorted into bins along each axis and the cost of
+/// splitting between every two neighbouring bins is estimated from the
+/// surface areas of the bins on either side. The primitives are partitioned
+/// at the cheapest split, whose position, axis and cost relative to intersecting
+/// a single object are returned. None is returned when all centroids coincide.
 ///
-/// We compare two bounding volumes based on their minimum
-/// slab and return the order with the volume with the
-/// least minimum first.
-fn box_compare(a: &Arc<dyn Hitable>,
-               b: &Arc<dyn Hitable>,
-               axis: usize,
-               start_time: f32,
-               end_time: f32)
-               -> Ordering {
-    if axis == 0 {
-        a.bounding_box(start_time, end_time)
-         .unwrap()
-         .minimum
-         .x()
-         .partial_cmp(&b.bounding_box(start_time, end_time).unwrap().minimum.x())
-         .unwrap()
-    } else if axis == 1 {
-        a.bounding_box(start_time, end_time)
-         .unwrap()
-         .minimum
-         .y()
-         .partial_cmp(&b.bounding_box(start_time, end_time).unwrap().minimum.y())
-         .unwrap()
-    } else {
-        a.bounding_box(start_time, end_time)
-         .unwrap()
-         .minimum
-         .z()
-         .partial_cmp(&b.bounding_box(start_time, end_time).unwrap().minimum.z())
-         .unwrap()
+/// Ingo Wald: On fast Construction of SAH-based Bounding Volume Hierarchies,
+/// IEEE Symposium on Interactive Ray Tracing, 2007
+fn sah_split(primitives: &mut [Primitive]) -> Option<(usize, usize, f32)> {
+    let bounds = primitives.iter()
+                           .fold(AABB::new(), |bbox, primitive| bbox.surrounding_box(&primitive.bbox));
+    let centroid_bounds = primitives.iter().fold(AABB::new(), |bbox, primitive| {
+                                               bbox.surrounding_box(&AABB::from(primitive.centroid,
+                                                                                primitive.centroid))
+                                           });
+    let extent = centroid_bounds.maximum - centroid_bounds.minimum;
+
+    let bin = |primitive: &Primitive, axis: usize| {
+        let offset = (primitive.centroid - centroid_bounds.minimum).as_ref()[axis] / extent.as_ref()[axis];
+        ((offset * SAH_BINS as f32) as usize).min(SAH_BINS - 1)
+    };
+
+    let mut best: Option<(usize, usize, f32)> = None;
+
+    for axis in 0..3 {
+        if extent.as_ref()[axis] <= 0.0 {
+            continue;
+        }
+
+        let mut counts = [0; SAH_BINS];
+        let mut boxes = vec![AABB::new(); SAH_BINS];
+
+        for primitive in primitives.iter() {
+            let b = bin(primitive, axis);
+            counts[b] += 1;
+            boxes[b] = boxes[b].surrounding_box(&primitive.bbox);
+        }
+
+        // sweep from the right to find the area and count above every split
+        let mut right_areas = [0.0; SAH_BINS];
+        let mut right_counts = [0; SAH_BINS];
+        let mut right_box = AABB::new();
+        let mut right_count = 0;
+        for b in (1..SAH_BINS).rev() {
+            right_box = right_box.surrounding_box(&boxes[b]);
+            right_count += counts[b];
+            right_areas[b] = right_box.surface_area();
+            right_counts[b] = right_count;
+        }
+
+        let mut left_box = AABB::new();
+        let mut left_count = 0;
+        for b in 0..SAH_BINS - 1 {
+            left_box = left_box.surrounding_box(&boxes[b]);
+            left_count += counts[b];
+
+            if left_count == 0 || right_counts[b + 1] == 0 {
+                continue;
+            }
+
+            let cost = TRAVERSAL_COST
+                       + (left_count as f32 * left_box.surface_area()
+                          + right_counts[b + 1] as f32 * right_areas[b + 1])
+                         / bounds.surface_area().max(f32::MIN_POSITIVE);
+
+            best = match best {
+                Some((_, _, best_cost)) if best_cost <= cost => best,
+                _ => Some((axis, b, cost)),
+            };
+        }
+    }
+
+    let (axis, split_bin, cost) = best?;
+
+    // move the primitives left of the split to the front
+    let mut middle = 0;
+    for i in 0..primitives.len() {
+        if bin(&primitives[i], axis) <= split_bin {
+            primitives.swap(i, middle);
+            middle += 1;
+        }
+    }
+
+    Some((middle, axis, cost))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use materials::Empty;
+    use sphere::Sphere;
+    use world::World;
+
+    #[test]
+    fn test_bvh_finds_closest_hit() {
+        let mut world = World::new();
+        for i in 0..200 {
+            let center = Vec3::new((i % 10) as f32, ((i / 10) % 5) as f32, (i / 50) as f32 * 3.0);
+            world.add(Sphere::new(center, center, 0.3 + 0.01 * (i % 7) as f32, Empty::new(), 0.0, 1.0));
+        }
+
+        let aabb = AABB::from(Vec3::zero(), Vec3::new(1.0, 3.0, 2.0));
+        assert_eq!(aabb.longest_axis(), 1);
+
+        let ray = Ray::new(Vec3::new(-2.0, 1.0, 1.0), Vec3::new(2.0, 0.0, 0.0), 0.0);
+        assert!(aabb.hit(&ray, 0.0, f32::MAX));
+        assert!(aabb.hit(&ray, 2.5, 2.9));
+        assert!(!aabb.hit(&ray, 0.0, 1.9));
+        assert!(!aabb.hit(&ray, 3.1, f32::MAX));
+
+        let trees = [BVH::with_split(&mut world.objects, 0.0, 1.0, SplitMethod::Median, 1),
+                     BVH::with_split(&mut world.objects, 0.0, 1.0, SplitMethod::SAH, 4)];
+
+        for i in 0..100 {
+            let origin = Vec3::new(-5.0, 0.05 * i as f32, -5.0);
+            let ray = Ray::new(origin, Vec3::new(1.0, 0.02 * i as f32 - 0.5, 0.9), 0.0);
+            let expected = world.hit(&ray, 1e-4, f32::MAX).map(|hit| hit.parameter);
+
+            for bvh in trees.iter() {
+                assert_eq!(bvh.hit(&ray, 1e-4, f32::MAX).map(|hit| hit.parameter), expected);
+            }
+        }
     }
 }
@@ -1,11 +1,10 @@
 use std::f32::consts::PI;
 
 use glam::Vec3;
-use rand::rngs::ThreadRng;
-use rand::Rng;
 
-use integrator::pick_sphere_point;
 use ray::Ray;
+use sampler::Sampler;
+use sampling::concentric_sample_disk;
 
 pub struct Camera {
     pub lower_left_corner: Vec3,
@@ -14,7 +13,6 @@ pub struct Camera {
     pub origin: Vec3,
     u: Vec3,
     v: Vec3,
-    w: Vec3,
     pub lens_radius: f32,
     pub start_time: f32,
     pub end_time: f32,
@@ -65,7 +63,6 @@ impl Camera {
                  origin,
                  u,
                  v,
-                 w,
                  lens_radius,
                  start_time,
                  end_time,
@@ -73,10 +70,13 @@ impl Camera {
     }
 
     /// Get the ray that is coming from the camera into the world
-    pub fn get_ray(&self, s: f32, t: f32, mut rng: &mut ThreadRng) -> Ray {
-        let radius: Vec3 = self.lens_radius * pick_sphere_point(&mut rng);
-        let offset: Vec3 = self.u * radius.x() + self.v * radius.y();
-        let time = self.start_time + rng.gen::<f32>() * (self.end_time - self.start_time);
+    ///
+    /// The sampler chooses the point on the lens and the time of the ray.
+    pub fn get_ray(&self, s: f32, t: f32, sampler: &mut dyn Sampler) -> Ray {
+        let (lens_u, lens_v) = sampler.get_2d();
+        let (disk_x, disk_y) = concentric_sample_disk(lens_u, lens_v);
+        let offset: Vec3 = self.lens_radius * (self.u * disk_x + self.v * disk_y);
+        let time = self.start_time + sampler.get_1d() * (self.end_time - self.start_time);
         Ray::new(self.origin + offset,
                  self.lower_left_corner + s * self.horizontal + t * self.vertical
                  - self.origin
@@ -0,0 +1,192 @@
+use std::fmt;
+use std::str::FromStr;
+
+use renderama::output::OutputFormat;
+use renderama::sampler::SamplerType;
+use renderama::scene;
+
+/// The render options selected on the command line
+pub struct Options {
+    pub scene: String,
+    pub width: usize,
+    pub height: usize,
+    pub samples: u32,
+    pub bounces: u32,
+    pub sampler: SamplerType,
+    pub seed: u64,
+    pub output: String,
+    pub format: OutputFormat,
+    pub denoise: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options { scene: String::from("cornell_box"),
+                  width: 2048,
+                  height: 2048,
+                  samples: 64,
+                  bounces: 10,
+                  sampler: SamplerType::Sobol,
+                  seed: 0,
+                  output: String::from("render.hdr"),
+                  format: OutputFormat::HDR,
+                  denoise: false }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CliError {
+    Help,
+    Invalid(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::Help => write!(f, "help requested"),
+            CliError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// Build the usage message printed with --help and on invalid input
+pub fn usage() -> String {
+    let defaults = Options::default();
+    format!("Usage: renderama [OPTIONS]
+
+Options:
+    --scene <NAME|FILE>  scene to render, by name, TOML scene file or glTF file [default: {}]
+    --width <PIXELS>     width of the image [default: {}]
+    --height <PIXELS>    height of the image [default: {}]
+    --samples <N>        samples per pixel [default: {}]
+    --bounces <N>        maximum bounces per path [default: {}]
+    --sampler <SAMPLER>  independent, stratified, halton or sobol [default: sobol]
+    --seed <N>           seed of the samples and random scenes [default: {}]
+    --output <PATH>      path of the rendered image [default: {}]
+    --format <FORMAT>    image format, hdr or png [default: from output extension]
+    --denoise            also write a denoised image (requires the denoise feature)
+    --help               print this message
+
+Scenes:
+    {}",
+            defaults.scene,
+            defaults.width,
+            defaults.height,
+            defaults.samples,
+            defaults.bounces,
+            defaults.seed,
+            defaults.output,
+            scene::SCENES.join("\n    "))
+}
+
+/// Parse the command line arguments, excluding the program name
+pub fn parse(args: &[String]) -> Result<Options, CliError> {
+    let mut options = Options::default();
+    let mut format = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--help" | "-h" => return Err(CliError::Help),
+            "--denoise" => options.denoise = true,
+            "--scene" => options.scene = value(arg, args.next())?.to_string(),
+            "--width" => options.width = positive(arg, value(arg, args.next())?)? as usize,
+            "--height" => options.height = positive(arg, value(arg, args.next())?)? as usize,
+            "--samples" => options.samples = positive(arg, value(arg, args.next())?)?,
+            "--bounces" => options.bounces = number(arg, value(arg, args.next())?)?,
+            "--sampler" => {
+                let name = value(arg, args.next())?;
+                options.sampler = SamplerType::from_name(name).ok_or_else(|| {
+                                      CliError::Invalid(format!("unknown sampler '{}', expected \
+                                                                 independent, stratified, halton \
+                                                                 or sobol",
+                                                                name))
+                                  })?;
+            }
+            "--seed" => options.seed = number(arg, value(arg, args.next())?)?,
+            "--output" => options.output = value(arg, args.next())?.to_string(),
+            "--format" => {
+                let name = value(arg, args.next())?;
+                format = Some(OutputFormat::from_name(name).ok_or_else(|| {
+                                  CliError::Invalid(format!("unknown format '{}', expected hdr or png",
+                                                            name))
+                              })?);
+            }
+            _ => return Err(CliError::Invalid(format!("unrecognized argument '{}'", arg))),
+        }
+    }
+
+    let is_file = [".toml", ".gltf", ".glb"].iter().any(|extension| options.scene.ends_with(extension));
+    if !scene::SCENES.contains(&options.scene.as_str()) && !is_file {
+        return Err(CliError::Invalid(format!("unknown scene '{}'", options.scene)));
+    }
+
+    options.format = match (format, OutputFormat::from_path(&options.output)) {
+        (Some(format), _) | (None, Some(format)) => format,
+        (None, None) => {
+            return Err(CliError::Invalid(format!("cannot infer the format of '{}', use --format",
+                                                 options.output)))
+        }
+    };
+
+    if options.denoise && !cfg!(feature = "denoise") {
+        return Err(CliError::Invalid(String::from("--denoise requires renderama to be built \
+                                                   with the denoise feature")));
+    }
+
+    Ok(options)
+}
+
+fn value<'a>(flag: &str, value: Option<&'a String>) -> Result<&'a str, CliError> {
+    value.map(|v| v.as_str())
+         .ok_or_else(|| CliError::Invalid(format!("{} requires a value", flag)))
+}
+
+fn number<T: FromStr>(flag: &str, value: &str) -> Result<T, CliError> {
+    value.parse()
+         .map_err(|_| CliError::Invalid(format!("{} expects a whole number, got '{}'", flag, value)))
+}
+
+fn positive(flag: &str, value: &str) -> Result<u32, CliError> {
+    match number(flag, value)? {
+        0 => Err(CliError::Invalid(format!("{} must be greater than zero", flag))),
+        n => Ok(n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_arguments() {
+        let options = parse(&args("--scene earth --width 320 --height 240 --samples 4 \
+                                   --bounces 3 --sampler halton --seed 42 --output earth.png"))
+                      .unwrap();
+
+        assert_eq!(options.scene, "earth");
+        assert_eq!((options.width, options.height), (320, 240));
+        assert_eq!((options.samples, options.bounces), (4, 3));
+        assert_eq!(options.sampler, SamplerType::Halton);
+        assert_eq!(options.seed, 42);
+        assert_eq!(options.format, OutputFormat::PNG);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse(&args("--help")).err(), Some(CliError::Help));
+        assert!(parse(&args("--scene teapot")).is_err());
+        assert!(parse(&args("--scene scenes/teapot.toml")).is_ok());
+        assert!(parse(&args("--scene teapot.glb")).is_ok());
+        assert!(parse(&args("--samples")).is_err());
+        assert!(parse(&args("--sampler random")).is_err());
+        assert!(parse(&args("--seed -1")).is_err());
+        assert!(parse(&args("--width 0")).is_err());
+        assert!(parse(&args("--output render.exr")).is_err());
+        assert!(parse(&args("--output render.exr --format png")).is_ok());
+    }
+}
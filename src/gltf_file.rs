@@ -0,0 +1,551 @@
+use std::f32;
+use std::sync::Arc;
+
+use glam::{Mat4, Vec2, Vec3};
+use gltf;
+use gltf::camera::Projection;
+use gltf::image::Format;
+use gltf::khr_lights_punctual::Kind;
+use gltf::material::AlphaMode;
+use gltf::mesh::Mode;
+
+use aabb::AABB;
+use bvh::BVH;
+use camera::Camera;
+use hitable::Hitable;
+use lights::{LightList, LightSelection};
+use materials::{Diffuse, Light, Material, Principled};
+use scene::Scene;
+use scene_file::SceneError;
+use sphere::Sphere;
+use texture::{ConstantTexture, FloatTexture, ImageTexture, Texture};
+use triangle::{MeshData, Normals, TriangleMesh};
+use utils::luminance;
+
+/// The radius of point lights relative to the size of the scene
+const POINT_LIGHT_RADIUS: f32 = 0.005;
+
+/// The distance of directional lights relative to the size of the scene
+const DIRECTIONAL_LIGHT_DISTANCE: f32 = 100.0;
+
+/// The sine of the angle directional lights cover as seen from the scene
+const DIRECTIONAL_LIGHT_SINE: f32 = 0.01;
+
+/// The red, green and blue channels of an image in their own order
+const RGB: [usize; 3] = [0, 1, 2];
+
+/// A punctual light placed by a node, with its color scaled by its intensity
+enum PunctualLight {
+    Point { position: Vec3, power: Vec3 },
+    Directional { direction: Vec3, power: Vec3 },
+}
+
+/// Importer collects the objects of the scene while the node hierarchy is walked
+struct Importer {
+    buffers: Vec<gltf::buffer::Data>,
+    materials: Vec<Arc<dyn Material>>,
+    normal_maps: Vec<Option<Arc<dyn Texture>>>,
+    // the set of texture coordinates that the textures of each material read
+    tex_coords: Vec<u32>,
+    objects: Vec<Arc<dyn Hitable>>,
+    bounds: Option<AABB>,
+    camera: Option<(Mat4, f32)>,
+    lights: Vec<PunctualLight>,
+}
+
+/// Load a glTF 2.0 file, either .gltf or .glb, into a scene
+///
+/// The nodes of the default scene are placed with their transforms, which
+/// are applied to the vertices of their meshes. Metallic-roughness materials
+/// become Principled materials with their base color, metallic-roughness,
+/// emissive and normal textures, read at the texture coordinates they name,
+/// where blended materials let the light their alpha does not cover through,
+/// and vertex colors tint the base color. The first perspective camera views
+/// the scene, or a camera in front of the scene when the file has none.
+///
+/// Punctual lights become small spheres that are sampled as lights, with
+/// spot lights shining in every direction and directional lights far away.
+/// Scenes without punctual lights are lit by the sky.
+pub fn load(filepath: &str, width: usize, height: usize) -> Result<Scene, SceneError> {
+    let (document, buffers, images) =
+        gltf::import(filepath).map_err(|error| SceneError::Invalid(format!("{}: {}", filepath, error)))?;
+
+    let mut importer = Importer { buffers,
+                                  materials: Vec::new(),
+                                  normal_maps: Vec::new(),
+                                  tex_coords: Vec::new(),
+                                  objects: Vec::new(),
+                                  bounds: None,
+                                  camera: None,
+                                  lights: Vec::new() };
+
+    for material in document.materials() {
+        importer.add_material(&material, &images)?;
+    }
+    // primitives without a material use the default material of glTF
+    importer.materials
+            .push(Arc::new(Diffuse::new(ConstantTexture::new(1.0, 1.0, 1.0), 0.0)));
+    importer.normal_maps.push(None);
+    importer.tex_coords.push(0);
+
+    let scene = document.default_scene()
+                        .or_else(|| document.scenes().next())
+                        .ok_or_else(|| SceneError::Invalid(format!("{} has no scene", filepath)))?;
+    for node in scene.nodes() {
+        importer.visit(&node, Mat4::identity())?;
+    }
+
+    let bounds = importer.bounds
+                         .clone()
+                         .unwrap_or_else(|| AABB::from(Vec3::splat(-1.0), Vec3::splat(1.0)));
+    let center = 0.5 * (bounds.minimum + bounds.maximum);
+    let size = (bounds.maximum - bounds.minimum).length().max(1e-3);
+
+    let mut lights = LightList::new(LightSelection::Power);
+    for light in &importer.lights {
+        let (position, radius, radiance) = match light {
+            PunctualLight::Point { position, power } => {
+                let radius = POINT_LIGHT_RADIUS * size;
+                // a sphere of radiance L has an intensity of L pi r^2 in every direction
+                (*position, radius, *power / (f32::consts::PI * radius * radius))
+            }
+            PunctualLight::Directional { direction, power } => {
+                let distance = DIRECTIONAL_LIGHT_DISTANCE * size;
+                // the irradiance of a distant sphere of radiance L is L pi sin^2
+                (center - *direction * distance,
+                 DIRECTIONAL_LIGHT_SINE * distance,
+                 *power / (f32::consts::PI * DIRECTIONAL_LIGHT_SINE * DIRECTIONAL_LIGHT_SINE))
+            }
+        };
+
+        let sphere = Sphere::new(position,
+                                 position,
+                                 radius,
+                                 Light::new(ConstantTexture::new(radiance.x(), radiance.y(), radiance.z())),
+                                 0.0,
+                                 1.0);
+        let power = luminance(&radiance) * sphere.area();
+        let sphere: Arc<dyn Hitable> = Arc::new(sphere);
+        importer.objects.push(sphere.clone());
+        lights.add_box(sphere, power);
+    }
+
+    let aspect = width as f32 / height as f32;
+    let atmosphere = importer.lights.is_empty();
+    let camera = match importer.camera {
+        Some((transform, fov)) => {
+            let origin = transform.transform_point3(Vec3::zero());
+            let forward = transform.transform_vector3(Vec3::new(0.0, 0.0, -1.0));
+            let up = transform.transform_vector3(Vec3::new(0.0, 1.0, 0.0));
+            Camera::new(origin, origin + forward, up, fov, aspect, 0.0, 1.0, 0.0, 1.0, atmosphere)
+        }
+        None => Camera::new(center + Vec3::new(0.0, 0.0, 1.2 * size),
+                            center,
+                            Vec3::new(0.0, 1.0, 0.0),
+                            45.0,
+                            aspect,
+                            0.0,
+                            1.0,
+                            0.0,
+                            1.0,
+                            atmosphere),
+    };
+
+    let name = scene.name().unwrap_or(filepath);
+    let bvh = BVH::new(&mut importer.objects, 0.0, 1.0);
+
+    Ok(Scene::new(name, camera, bvh, lights))
+}
+
+impl Importer {
+    /// Convert a metallic-roughness material into a principled material
+    ///
+    /// The base color, metallic, roughness and emission come from their
+    /// textures or factors, and blended materials transmit the light their
+    /// alpha does not cover. Emissive materials are not added to the lights
+    /// of the scene, so they are only found by paths that hit them. The
+    /// normal map of the material is kept with it.
+    ///
+    /// Meshes carry a single set of texture coordinates, so the textures of
+    /// a material must all name the same set.
+    fn add_material(&mut self,
+                    material: &gltf::Material,
+                    images: &[gltf::image::Data])
+                    -> Result<(), SceneError> {
+        let pbr = material.pbr_metallic_roughness();
+        let mut tex_coords: Vec<u32> =
+            vec![pbr.base_color_texture().map(|info| info.tex_coord()),
+                 pbr.metallic_roughness_texture().map(|info| info.tex_coord()),
+                 material.emissive_texture().map(|info| info.tex_coord()),
+                 material.normal_texture().map(|normal| normal.tex_coord())].into_iter()
+                                                                            .flatten()
+                                                                            .collect();
+        tex_coords.dedup();
+        if tex_coords.len() > 1 {
+            return Err(SceneError::Invalid(format!("the textures of material {} read more than one set \
+                                                    of texture coordinates",
+                                                   material.index().unwrap_or_default())));
+        }
+
+        let base_color = pbr.base_color_factor();
+        let color = Vec3::new(base_color[0], base_color[1], base_color[2]);
+        let emission = Vec3::from(material.emissive_factor());
+        let constant = |color: Vec3| -> Arc<dyn Texture> {
+            Arc::new(ConstantTexture::new(color.x(), color.y(), color.z()))
+        };
+
+        let normal_map = match material.normal_texture() {
+            Some(normal) => Some(texture(&images[normal.texture().source().index()], RGB, Vec3::one())?),
+            None => None,
+        };
+
+        let albedo = match pbr.base_color_texture() {
+            Some(info) => texture(&images[info.texture().source().index()], RGB, color)?,
+            None => constant(color),
+        };
+        let mut converted = Principled::from_box(albedo);
+
+        // metallic is kept in the blue channel and roughness in the green channel
+        let metallic = Vec3::splat(pbr.metallic_factor());
+        let roughness = Vec3::splat(pbr.roughness_factor());
+        match pbr.metallic_roughness_texture() {
+            Some(info) => {
+                let image = &images[info.texture().source().index()];
+                converted.metallic = texture(image, [2, 2, 2], metallic)?;
+                converted.roughness = texture(image, [1, 1, 1], roughness)?;
+            }
+            None => {
+                converted.metallic = constant(metallic);
+                converted.roughness = constant(roughness);
+            }
+        }
+
+        converted.emission = match material.emissive_texture() {
+            Some(info) => texture(&images[info.texture().source().index()], RGB, emission)?,
+            None => constant(emission),
+        };
+
+        if material.alpha_mode() == AlphaMode::Blend && base_color[3] < 1.0 {
+            converted.transmission = constant(Vec3::splat(1.0 - base_color[3]));
+        }
+
+        self.materials.push(Arc::new(converted));
+        self.normal_maps.push(normal_map);
+        self.tex_coords.push(tex_coords.first().cloned().unwrap_or(0));
+        Ok(())
+    }
+
+    /// Add the objects of a node and its children, which are placed by the given transform
+    fn visit(&mut self, node: &gltf::Node, parent: Mat4) -> Result<(), SceneError> {
+        let transform = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+
+        if let Some(mesh) = node.mesh() {
+            self.add_mesh(&mesh, transform)?;
+        }
+
+        if let Some(camera) = node.camera() {
+            if let (None, Projection::Perspective(perspective)) = (&self.camera, camera.projection()) {
+                self.camera = Some((transform, perspective.yfov().to_degrees()));
+            }
+        }
+
+        if let Some(light) = node.light() {
+            let power = light.intensity() * Vec3::from(light.color());
+            self.lights.push(match light.kind() {
+                                 Kind::Directional => {
+                                     let direction = transform.transform_vector3(Vec3::new(0.0, 0.0, -1.0));
+                                     PunctualLight::Directional { direction: direction.normalize(),
+                                                                  power }
+                                 }
+                                 Kind::Point | Kind::Spot { .. } => {
+                                     PunctualLight::Point { position: transform.transform_point3(Vec3::zero()),
+                                                            power }
+                                 }
+                             });
+        }
+
+        for child in node.children() {
+            self.visit(&child, transform)?;
+        }
+
+        Ok(())
+    }
+
+    /// Add the triangles of every primitive of a mesh, with the transform applied to their vertices
+    fn add_mesh(&mut self, mesh: &gltf::Mesh, transform: Mat4) -> Result<(), SceneError> {
+        let name = mesh.name().unwrap_or("unnamed");
+        let invalid = |message: String| SceneError::Invalid(format!("mesh '{}': {}", name, message));
+
+        let normal_transform = transform.inverse().transpose();
+        // a mirroring transform turns the triangles around, so their winding is reversed
+        let mirrored = transform.determinant() < 0.0;
+
+        let mut data = MeshData::default();
+
+        for primitive in mesh.primitives() {
+            // points and lines have no surface to render
+            if primitive.mode() != Mode::Triangles {
+                continue;
+            }
+
+            let buffers = &self.buffers;
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+            let positions: Vec<Vec3> =
+                reader.read_positions()
+                      .ok_or_else(|| invalid(String::from("a primitive has no positions")))?
+                      .map(|p| transform.transform_point3(Vec3::from(p)))
+                      .collect();
+            let mut indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+            if indices.len() / 3 * 3 != indices.len() {
+                return Err(invalid(format!("{} indices do not form whole triangles", indices.len())));
+            }
+            if mirrored {
+                for triangle in indices.chunks_exact_mut(3) {
+                    triangle.swap(1, 2);
+                }
+            }
+
+            let material_id = primitive.material().index().unwrap_or(self.materials.len() - 1);
+
+            let primitive_data =
+                MeshData { normals: match reader.read_normals() {
+                               Some(normals) => normals.map(|n| {
+                                                           normal_transform.transform_vector3(Vec3::from(n))
+                                                                           .normalize()
+                                                       })
+                                                       .collect(),
+                               None => Vec::new(),
+                           },
+                           // glTF puts the origin of its texture coordinates at the top of the image
+                           uvs: match reader.read_tex_coords(self.tex_coords[material_id]) {
+                               Some(uvs) => uvs.into_f32().map(|t| Vec2::new(t[0], 1.0 - t[1])).collect(),
+                               None => Vec::new(),
+                           },
+                           tangents: match reader.read_tangents() {
+                               Some(tangents) => tangents.map(|t| {
+                                                             transform.transform_vector3(Vec3::new(t[0], t[1], t[2]))
+                                                                      .normalize()
+                                                         })
+                                                         .collect(),
+                               None => Vec::new(),
+                           },
+                           colors: match reader.read_colors(0) {
+                               Some(colors) => colors.into_rgb_f32().map(Vec3::from).collect(),
+                               None => Vec::new(),
+                           },
+                           positions,
+                           indices,
+                           material_ids: Vec::new() };
+            primitive_data.validate().map_err(|error| invalid(error.to_string()))?;
+
+            let mut primitive_data = primitive_data.with_normals(Normals::Flat);
+            primitive_data.material_ids = vec![material_id as u32; primitive_data.triangle_count()];
+
+            data.append(primitive_data);
+        }
+
+        if data.triangle_count() == 0 {
+            return Ok(());
+        }
+
+        for position in &data.positions {
+            let point = AABB::from(*position, *position);
+            self.bounds = Some(match &self.bounds {
+                                   Some(bounds) => bounds.surrounding_box(&point),
+                                   None => point,
+                               });
+        }
+
+        let mesh = TriangleMesh::with_normal_maps(data, self.materials.clone(), self.normal_maps.clone())
+            .map_err(|error| invalid(error.to_string()))?;
+        self.objects.push(Arc::new(mesh));
+
+        Ok(())
+    }
+}
+
+/// Create a texture from the pixels of an image, scaled by the given color
+///
+/// The red, green and blue channels of the texture are read from the
+/// channels of the image at the given indices. Images of 16 bits per
+/// channel keep their precision in a float texture, while those of 8 bits
+/// are stored as bytes again, which is all the precision they had.
+fn texture(image: &gltf::image::Data,
+           indices: [usize; 3],
+           scale: Vec3)
+           -> Result<Arc<dyn Texture>, SceneError> {
+    // the offsets of the red, green and blue channels and the size of a pixel in bytes
+    let (channels, pixel_size, channel_size) = match image.format {
+        Format::R8 => ([0, 0, 0], 1, 1),
+        Format::R8G8 => ([0, 1, 1], 2, 1),
+        Format::R8G8B8 => ([0, 1, 2], 3, 1),
+        Format::R8G8B8A8 => ([0, 1, 2], 4, 1),
+        Format::B8G8R8 => ([2, 1, 0], 3, 1),
+        Format::B8G8R8A8 => ([2, 1, 0], 4, 1),
+        Format::R16 => ([0, 0, 0], 2, 2),
+        Format::R16G16 => ([0, 2, 2], 4, 2),
+        Format::R16G16B16 => ([0, 2, 4], 6, 2),
+        Format::R16G16B16A16 => ([0, 2, 4], 8, 2),
+    };
+    let scale = scale.as_ref();
+
+    let read = |pixel: &[u8], c: usize| {
+        let offset = channels[indices[c]];
+        let value = if channel_size == 2 {
+            f32::from(u16::from_ne_bytes([pixel[offset], pixel[offset + 1]])) / 65535.0
+        } else {
+            f32::from(pixel[offset]) / 255.0
+        };
+        value * scale[c]
+    };
+    let colors = image.pixels
+                      .chunks(pixel_size)
+                      .map(|pixel| Vec3::new(read(pixel, 0), read(pixel, 1), read(pixel, 2)));
+
+    let texture: Option<Arc<dyn Texture>> = if channel_size == 2 {
+        FloatTexture::from_pixels(image.width, image.height, colors.collect())
+            .map(|texture| Arc::new(texture) as Arc<dyn Texture>)
+    } else {
+        let pixels = colors.flat_map(|color| {
+                               let color = (color * 255.0).min(Vec3::splat(255.0));
+                               vec![color.x().round() as u8, color.y().round() as u8, color.z().round() as u8]
+                           })
+                           .collect();
+        ImageTexture::from_pixels(image.width, image.height, pixels)
+            .map(|texture| Arc::new(texture) as Arc<dyn Texture>)
+    };
+
+    texture.ok_or_else(|| {
+               SceneError::Invalid(format!("an image of {} x {} pixels has too few pixels",
+                                           image.width, image.height))
+           })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_load_gltf_scene() {
+        // a triangle facing the camera, which sits behind it next to a point light
+        let gltf = r#"{
+            "asset": { "version": "2.0" },
+            "scene": 0,
+            "scenes": [{ "name": "triangle", "nodes": [0, 1] }],
+            "nodes": [
+                { "mesh": 0, "translation": [0.0, 0.0, -1.0] },
+                { "translation": [0.0, 0.0, 4.0], "children": [2, 3] },
+                { "camera": 0 },
+                { "translation": [1.0, 0.0, 0.0],
+                  "extensions": { "KHR_lights_punctual": { "light": 0 } } }
+            ],
+            "cameras": [{ "type": "perspective",
+                          "perspective": { "yfov": 0.8, "znear": 0.1 } }],
+            "extensionsUsed": ["KHR_lights_punctual"],
+            "extensions": { "KHR_lights_punctual": {
+                "lights": [{ "type": "point", "color": [1.0, 1.0, 1.0], "intensity": 10.0 }]
+            } },
+            "materials": [{ "pbrMetallicRoughness": { "baseColorFactor": [0.8, 0.2, 0.2, 1.0],
+                                                      "metallicFactor": 0.0 } }],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 }, "material": 0 }] }],
+            "accessors": [{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                            "min": [-1.0, -1.0, 0.0], "max": [1.0, 1.0, 0.0] }],
+            "bufferViews": [{ "buffer": 0, "byteLength": 36 }],
+            "buffers": [{ "byteLength": 36,
+                          "uri": "data:application/octet-stream;base64,AACAvwAAgL8AAAAAAACAPwAAgL8AAAAAAAAAAAAAgD8AAAAA" }]
+        }"#;
+
+        let load_str = |gltf: &str| {
+            let path = env::temp_dir().join(format!("renderama-gltf-{}.gltf", std::process::id()));
+            fs::write(&path, gltf).unwrap();
+            let scene = load(path.to_str().unwrap(), 64, 64);
+            fs::remove_file(&path).unwrap();
+            scene
+        };
+        let scene = load_str(gltf).unwrap();
+
+        assert_eq!(scene.name, "triangle");
+        assert_eq!(scene.lights.len(), 1);
+
+        // the transformed triangle is hit with its flat normal
+        let ray = ::ray::Ray::new(Vec3::new(0.0, 0.0, 4.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = scene.world.hit(&ray, 1e-4, f32::MAX).unwrap();
+        assert!((hit.parameter - 5.0).abs() < 1e-4);
+        assert_eq!(hit.shading_normal, Vec3::new(0.0, 0.0, 1.0));
+
+        let light = scene.lights.bounding_box(0.0, 1.0).unwrap();
+        assert!((0.5 * (light.minimum + light.maximum) - Vec3::new(1.0, 0.0, 4.0)).length() < 1e-4);
+
+        // a mirrored mesh of four vertices does not form whole triangles
+        let mirrored = gltf.replace(r#""mesh": 0,"#, r#""mesh": 0, "scale": [-1.0, 1.0, 1.0],"#)
+                           .replace(r#""count": 3"#, r#""count": 4"#)
+                           .replace(r#""byteLength": 36"#, r#""byteLength": 48"#)
+                           .replace("AAAAAAAAgD8AAAAA\"", "AAAAAAAAgD8AAAAAAAAAAAAAgL8AAAAA\"");
+        match load_str(&mirrored) {
+            Err(SceneError::Invalid(message)) => assert!(message.contains("whole triangles")),
+            _ => panic!("a mesh of four vertices was loaded"),
+        }
+    }
+
+    #[test]
+    fn test_gltf_texture_coordinates() {
+        // a triangle whose base color reads the second set of texture coordinates, which is constant
+        let gltf = r#"{
+            "asset": { "version": "2.0" },
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "mesh": 0 }],
+            "materials": [{ "pbrMetallicRoughness": { "baseColorTexture": { "index": 0, "texCoord": 1 } } }],
+            "textures": [{ "source": 0 }],
+            "images": [{ "uri": "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAIAAACQd1PeAAAADElEQVR4nGP438AAAAQBAYDFKhhdAAAAAElFTkSuQmCC" }],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0, "TEXCOORD_0": 1, "TEXCOORD_1": 2 },
+                                          "material": 0 }] }],
+            "accessors": [{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                            "min": [-1.0, -1.0, 0.0], "max": [1.0, 1.0, 0.0] },
+                          { "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2" },
+                          { "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC2" }],
+            "bufferViews": [{ "buffer": 0, "byteLength": 36 },
+                            { "buffer": 0, "byteOffset": 36, "byteLength": 24 },
+                            { "buffer": 0, "byteOffset": 60, "byteLength": 24 }],
+            "buffers": [{ "byteLength": 84,
+                          "uri": "data:application/octet-stream;base64,AACAvwAAgL8AAAAAAACAPwAAgL8AAAAAAAAAAAAAgD8AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAACAPgAAgD4AAIA+AACAPgAAgD4AAIA+" }]
+        }"#;
+
+        let load_str = |gltf: &str| {
+            let path = env::temp_dir().join(format!("renderama-gltf-uv-{}.gltf", std::process::id()));
+            fs::write(&path, gltf).unwrap();
+            let scene = load(path.to_str().unwrap(), 64, 64);
+            fs::remove_file(&path).unwrap();
+            scene
+        };
+        let scene = load_str(gltf).unwrap();
+
+        let ray = ::ray::Ray::new(Vec3::new(0.0, 0.0, 4.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = scene.world.hit(&ray, 1e-4, f32::MAX).unwrap();
+        assert!((hit.u - 0.25).abs() < 1e-5 && (hit.v - 0.75).abs() < 1e-5);
+
+        // a normal map that reads the first set cannot share the mesh with the base color
+        let mixed = gltf.replace(r#""texCoord": 1 } }"#, r#""texCoord": 1 } }, "normalTexture": { "index": 0 }"#);
+        match load_str(&mixed) {
+            Err(SceneError::Invalid(message)) => assert!(message.contains("more than one set")),
+            _ => panic!("a material with two sets of texture coordinates was loaded"),
+        }
+    }
+
+    #[test]
+    fn test_sixteen_bit_texture() {
+        // 1000 / 65535 lies between two values of 8 bits, so only a float texture keeps it
+        let image = gltf::image::Data { pixels: 1000u16.to_ne_bytes().to_vec(),
+                                        format: Format::R16,
+                                        width: 1,
+                                        height: 1 };
+        let texture = texture(&image, RGB, Vec3::one()).unwrap();
+        let color = texture.value(0.5, 0.5, &Vec3::zero());
+        assert!((color - Vec3::splat(1000.0 / 65535.0)).length() < 1e-6);
+    }
+}
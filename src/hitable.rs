@@ -1,11 +1,11 @@
 use std::sync::Arc;
 
-use glam::Vec3;
-use rand::rngs::ThreadRng;
+use glam::{Vec2, Vec3};
 
 use aabb::AABB;
 use materials::Material;
 use ray::Ray;
+use sampler::Sampler;
 
 /// HitRecord contains the elements necessary to render geometry
 /// once a ray has hit that geometry.
@@ -16,7 +16,13 @@ pub struct HitRecord {
     pub point: Vec3,
     pub geometric_normal: Vec3,
     pub shading_normal: Vec3,
+    /// The direction in which the u texture coordinate increases, if the surface has one
+    pub tangent: Option<Vec3>,
     pub material: Arc<dyn Material>,
+    /// The barycentric coordinates of a hit on a triangle, kept for debug output
+    pub barycentric: Option<Vec2>,
+    /// The interpolated vertex color of a hit on a mesh with colors, which tints its material
+    pub vertex_color: Option<Vec3>,
 }
 
 impl HitRecord {
@@ -35,7 +41,10 @@ impl HitRecord {
                     point: point,
                     geometric_normal: geometric_normal,
                     shading_normal: shading_normal,
-                    material: material }
+                    tangent: None,
+                    material: material,
+                    barycentric: None,
+                    vertex_color: None }
     }
 }
 
@@ -50,15 +59,23 @@ pub trait Hitable: Send + Sync {
 
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB>;
 
-    fn pdf_value(&self, _origin: Vec3, _v: Vec3) -> f32 {
+    /// The solid angle density of sampling direction v from origin with pdf_random
+    ///
+    /// The time is that of the ray leaving the origin so that moving
+    /// geometry is sampled where it is at that time.
+    fn pdf_value(&self, _origin: Vec3, _v: Vec3, _time: f32) -> f32 {
         0.0
     }
 
-    fn pdf_random(&self, _origin: Vec3, _rng: &mut ThreadRng) -> Vec3 {
-        Vec3::new(1.0, 0.0, 0.0)
+    /// Sample a direction from origin towards the geometry
+    ///
+    /// None is returned when the geometry can't be sampled from origin.
+    fn pdf_random(&self, _origin: Vec3, _time: f32, _sampler: &mut dyn Sampler) -> Option<Vec3> {
+        None
     }
 }
 
+#[derive(Clone)]
 pub struct FlipNormals {
     hitable: Arc<dyn Hitable>,
 }
@@ -68,6 +85,10 @@ impl FlipNormals {
         let hitable = Arc::new(hitable);
         FlipNormals { hitable }
     }
+
+    pub fn from_box(hitable: Arc<dyn Hitable>) -> FlipNormals {
+        FlipNormals { hitable }
+    }
 }
 
 impl Hitable for FlipNormals {
@@ -84,4 +105,12 @@ impl Hitable for FlipNormals {
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
         self.hitable.bounding_box(t0, t1)
     }
+
+    fn pdf_value(&self, origin: Vec3, v: Vec3, time: f32) -> f32 {
+        self.hitable.pdf_value(origin, v, time)
+    }
+
+    fn pdf_random(&self, origin: Vec3, time: f32, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        self.hitable.pdf_random(origin, time, sampler)
+    }
 }
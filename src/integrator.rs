@@ -1,106 +1,201 @@
 use std::f32;
-use std::sync::Arc;
 
 use glam::Vec3;
-use rand::rngs::ThreadRng;
-use rand::Rng;
-use rand_distr::{Distribution, Normal};
 
 use bvh::BVH;
-use hitable::Hitable;
+use hitable::{HitRecord, Hitable};
+use lights::LightList;
 use pdf::PDF;
-use plane::Plane;
 use ray::{find_offset_point, Ray};
+use sampler::Sampler;
+use sampling::uniform_sample_sphere;
 
 /// Pick a random point on the unit sphere
 ///
-/// We can use a Gaussian distribution to uniformly generate points
-/// on the unit sphere. If a uniform distribution were used instead,
-/// the points would tend to aggregate to the poles of the sphere.
-/// A vector is created from the sample points taken for each coordinate
-/// axis and the unit vector of this newly created vector is returned.
+/// The point is mapped from a pair of sample values so that points
+/// are uniformly distributed over the sphere rather than aggregating
+/// to the poles of the sphere.
 ///
 /// Reference: http://mathworld.wolfram.com/SpherePointPicking.html
 ///
-pub fn pick_sphere_point(rng: &mut ThreadRng) -> Vec3 {
-    let normal_distribution = Normal::new(0.0, 1.0).unwrap();
-    let x = normal_distribution.sample(rng) as f32;
-    let y = normal_distribution.sample(rng) as f32;
-    let z = normal_distribution.sample(rng) as f32;
+pub fn pick_sphere_point(sampler: &mut dyn Sampler) -> Vec3 {
+    let (u, v) = sampler.get_2d();
+    uniform_sample_sphere(u, v)
+}
 
-    Vec3::new(x, y, z).normalize()
+/// Heuristic weights the samples of light and BSDF sampling in multiple importance sampling
+///
+/// Eric Veach, Leonidas J. Guibas: Optimally Combining Sampling Techniques
+/// for Monte Carlo Rendering, SIGGRAPH 1995
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Heuristic {
+    Balance,
+    Power,
+}
+
+impl Heuristic {
+    /// Weight a sample taken with pdf against the other strategy with other_pdf
+    ///
+    /// The weights of both strategies for the same direction sum to one.
+    pub fn weight(self, pdf: f32, other_pdf: f32) -> f32 {
+        let (pdf, other_pdf) = match self {
+            Heuristic::Balance => (pdf, other_pdf),
+            Heuristic::Power => (pdf * pdf, other_pdf * other_pdf),
+        };
+
+        if pdf + other_pdf > 0.0 {
+            pdf / (pdf + other_pdf)
+        } else {
+            0.0
+        }
+    }
 }
 
 /// Compute the color of the surface that the ray has collided with
 ///
 /// If the ray hits an object in the world, the object is colored in relation
 /// to the object's material. If the ray does not record a hit, then we compute
-/// the color of the atmosphere. We recursively call compute_color to sample
-/// the color at the ray's hit point. The depth has been set to an arbitrary
-/// limit of 50 which can lead to bias rendering.
+/// the color of the atmosphere.
 ///
+/// At every diffuse bounce the lights are sampled directly and the material
+/// is sampled for the next bounce. Light reached by either strategy is weighted
+/// with the heuristic so that each strategy covers the directions it samples well:
+/// light sampling for small bright lights and BSDF sampling for glossy surfaces.
 pub fn render_path_integrator(mut ray: Ray,
-                     world: &BVH,
-                     bounces: u32,
-                     light_source: &Plane,
-                     atmosphere: bool,
-                     rng: &mut ThreadRng)
-                     -> Vec3 {
+                              world: &BVH,
+                              bounces: u32,
+                              lights: &LightList,
+                              heuristic: Heuristic,
+                              atmosphere: bool,
+                              sampler: &mut dyn Sampler)
+                              -> Vec3 {
     let mut color = Vec3::zero();
     let mut throughput = Vec3::one();
 
+    // the density of the BSDF sample that created the ray, None for camera and specular rays
+    let mut scattering_density: Option<f32> = None;
+
     for bounce in 0..=bounces {
-        if let Some(hit_record) = world.hit(&ray, 1e-4, f32::MAX) {
-            let emitted = hit_record.material.emitted(&ray, &hit_record);
-            color += throughput * emitted;
-
-            if let Some(scatter_record) = hit_record.material.scatter(&ray, &hit_record, rng) {
-                if scatter_record.specular {
-                    throughput *= scatter_record.attenuation;
-                    ray = scatter_record.specular_ray;
-                } else {
-                    let hitable_pdf = PDF::HitablePDF { origin: hit_record.point,
-                                                        hitable: Arc::new(light_source.clone()) };
-                    let mixture_pdf = PDF::MixturePDF { cosine_pdf: &scatter_record.pdf,
-                                                        hitable_pdf: &hitable_pdf };
-
-                    let mut offset_point = hit_record.point;
-                    if hit_record.geometric_normal != hit_record.shading_normal {
-                        offset_point =
-                            find_offset_point(hit_record.point, hit_record.geometric_normal);
-                        offset_point += pick_sphere_point(rng);
-                    }
-                    let scattered = Ray::new(offset_point, mixture_pdf.generate(rng), ray.time);
-                    let pdf = mixture_pdf.value(scattered.direction);
-                    let scattering_pdf = hit_record.material
-                                                   .scattering_pdf(&ray, &hit_record, &scattered);
-
-                    throughput *= (scattering_pdf * scatter_record.attenuation) / pdf;
-
-                    ray = scattered;
+        ray = ray.with_medium_sample(sampler.get_1d());
+        let hit_record = match world.hit(&ray, 1e-4, f32::MAX) {
+            Some(hit_record) => hit_record,
+            None => {
+                if atmosphere {
+                    let point: f32 = 0.5 * (ray.direction.y() + 1.0);
+                    let lerp = (1.0 - point) * Vec3::splat(1.0) + point * Vec3::new(0.5, 0.7, 1.0);
+                    color += throughput * lerp;
                 }
-            } else {
                 break;
             }
+        };
+
+        let emitted = hit_record.material.emitted(&ray, &hit_record);
+        if emitted.max_element() > 0.0 {
+            let weight = match scattering_density {
+                Some(pdf) if !lights.is_empty() => {
+                    let light_pdf = lights.pdf_value(ray.origin, ray.direction, ray.time);
+                    heuristic.weight(pdf, light_pdf)
+                }
+                _ => 1.0,
+            };
+            color += throughput * emitted * weight;
+        }
+
+        let scatter_record = match hit_record.material.scatter(&ray, &hit_record, sampler) {
+            Some(scatter_record) => scatter_record,
+            None => break,
+        };
+
+        if scatter_record.specular {
+            throughput *= scatter_record.attenuation;
+            ray = scatter_record.specular_ray;
+            scattering_density = None;
         } else {
-            if atmosphere {
-                let point: f32 = 0.5 * (ray.direction.y() + 1.0);
-                let lerp = (1.0 - point) * Vec3::splat(1.0) + point * Vec3::new(0.5, 0.7, 1.0);
-                color = throughput * lerp;
-            } else {
-                color = Vec3::zero();
+            let mut offset_point = hit_record.point;
+            if hit_record.geometric_normal != hit_record.shading_normal {
+                offset_point = find_offset_point(hit_record.point, hit_record.geometric_normal);
+                offset_point += pick_sphere_point(sampler);
+            }
+
+            // the light reached by the BSDF sample of the last bounce is never
+            // added, so its light sample would not be weighted correctly
+            if bounce < bounces && !lights.is_empty() {
+                color += throughput
+                         * scatter_record.attenuation
+                         * sample_light(&ray,
+                                        &hit_record,
+                                        &scatter_record.pdf,
+                                        offset_point,
+                                        world,
+                                        lights,
+                                        heuristic,
+                                        sampler);
             }
+
+            let direction = match scatter_record.pdf.generate(sampler) {
+                Some(direction) => direction,
+                None => break,
+            };
+            let scattered = Ray::new(offset_point, direction, ray.time);
+            let pdf = scatter_record.pdf.value(scattered.direction);
+
+            if pdf <= 0.0 {
+                break;
+            }
+
+            let scattering = hit_record.material.scattering(&ray, &hit_record, &scattered);
+
+            throughput *= (scattering * scatter_record.attenuation) / pdf;
+            scattering_density = Some(pdf);
+
+            ray = scattered;
         }
 
         if bounce > 3 {
             let roulette_factor = (1.0 - throughput.max_element()).max(0.05);
-            if rng.gen::<f32>() < roulette_factor {
+            if sampler.get_1d() < roulette_factor {
                 break;
             }
             throughput /= 1.0 - roulette_factor;
         }
     }
-    return color;
+
+    color
+}
+
+/// Sample a light from the hit point and weight its contribution against BSDF sampling
+#[allow(clippy::too_many_arguments)]
+fn sample_light(ray: &Ray,
+                hit_record: &HitRecord,
+                scattering: &PDF,
+                origin: Vec3,
+                world: &BVH,
+                lights: &LightList,
+                heuristic: Heuristic,
+                sampler: &mut dyn Sampler)
+                -> Vec3 {
+    let direction = match lights.pdf_random(origin, ray.time, sampler) {
+        Some(direction) => direction,
+        None => return Vec3::zero(),
+    };
+
+    let light_ray = Ray::new(origin, direction, ray.time).with_medium_sample(sampler.get_1d());
+    let light_pdf = lights.pdf_value(origin, direction, ray.time);
+
+    if light_pdf <= 0.0 {
+        return Vec3::zero();
+    }
+
+    // the light is occluded unless the first surface along the ray emits towards the origin
+    let emitted = match world.hit(&light_ray, 1e-4, f32::MAX) {
+        Some(light_hit) => light_hit.material.emitted(&light_ray, &light_hit),
+        None => return Vec3::zero(),
+    };
+
+    let scattering_color = hit_record.material.scattering(ray, hit_record, &light_ray);
+    let weight = heuristic.weight(light_pdf, scattering.value(direction));
+
+    emitted * scattering_color * weight / light_pdf
 }
 
 pub fn render_normals(ray: Ray, world: &BVH) -> Vec3 {
@@ -112,3 +207,32 @@ pub fn render_normals(ray: Ray, world: &BVH) -> Vec3 {
         (1.0 - point) * Vec3::new(1.0, 1.0, 1.0) + point * Vec3::new(0.5, 0.7, 1.0)
     }
 }
+
+/// Color triangles by the barycentric coordinates of their hits and everything else black
+pub fn render_barycentrics(ray: Ray, world: &BVH) -> Vec3 {
+    match world.hit(&ray, 1e-4, f32::MAX).and_then(|hit| hit.barycentric) {
+        Some(barycentric) => Vec3::new(1.0 - barycentric.x() - barycentric.y(),
+                                       barycentric.x(),
+                                       barycentric.y()),
+        None => Vec3::zero(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heuristic_weights() {
+        for heuristic in &[Heuristic::Balance, Heuristic::Power] {
+            let light_weight = heuristic.weight(2.0, 0.5);
+            let scattering_weight = heuristic.weight(0.5, 2.0);
+
+            assert!((light_weight + scattering_weight - 1.0).abs() < 1e-6);
+            assert!(light_weight > scattering_weight);
+        }
+
+        assert!((Heuristic::Power.weight(2.0, 1.0) - 0.8).abs() < 1e-6);
+        assert_eq!(Heuristic::Balance.weight(0.0, 0.0), 0.0);
+    }
+}
@@ -0,0 +1,71 @@
+//! Renderama is a path tracer based off of Peter Shirley's Ray Tracing Minibook series.
+//!
+//! A scene is built from the objects in the modules below, either by hand,
+//! with one of the example scenes in `scene`, or from a scene file with
+//! `scene_file::load`, and then rendered into a `Film`:
+//!
+//! ```no_run
+//! use renderama::{render, scene, Settings};
+//!
+//! let settings = Settings::new(512, 512, 64, 10);
+//! let scene = scene::cornell_box_scene(settings.width, settings.height);
+//! let film = render(&scene, &settings);
+//! film.write("cornell_box.png", renderama::output::OutputFormat::PNG).unwrap();
+//! ```
+
+extern crate glam;
+extern crate gltf;
+extern crate image;
+extern crate image2;
+extern crate nalgebra;
+#[cfg(feature = "denoise")]
+extern crate oidn;
+extern crate rand;
+extern crate rayon;
+extern crate serde;
+extern crate tobj;
+extern crate toml;
+
+pub mod aabb;
+pub mod basis;
+pub mod bvh;
+pub mod camera;
+pub mod denoise;
+pub mod gltf_file;
+pub mod hitable;
+pub mod integrator;
+pub mod lights;
+pub mod materials;
+pub mod metrics;
+pub mod microfacet;
+pub mod obj;
+pub mod output;
+pub mod pdf;
+pub mod plane;
+pub mod ply;
+pub mod post;
+pub mod quad;
+pub mod ray;
+pub mod sampler;
+pub mod rectangle;
+pub mod renderer;
+pub mod sampling;
+pub mod scene;
+pub mod scene_file;
+pub mod sphere;
+pub mod texture;
+pub mod tone;
+pub mod transformations;
+pub mod triangle;
+pub mod utils;
+pub mod volume;
+pub mod world;
+
+pub use bvh::BVH;
+pub use camera::Camera;
+pub use hitable::{HitRecord, Hitable};
+pub use materials::Material;
+pub use renderer::{render, Film, Renderer, Settings};
+pub use scene::Scene;
+pub use texture::Texture;
+pub use world::World;
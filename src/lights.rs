@@ -0,0 +1,201 @@
+use std::f32;
+use std::sync::Arc;
+
+use glam::Vec3;
+
+use aabb::AABB;
+use hitable::{HitRecord, Hitable};
+use ray::Ray;
+use sampler::Sampler;
+
+/// LightSelection determines how a light is picked for next-event estimation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightSelection {
+    /// Every light is picked with the same probability
+    Uniform,
+    /// Lights are picked proportionally to the power they emit
+    Power,
+}
+
+/// LightList holds the emitters of a scene that are importance sampled
+///
+/// The list is itself a Hitable so that it can be sampled through
+/// PDF::HitablePDF. Sampling a direction first picks one light according
+/// to the selection strategy and then samples that light, so the density
+/// of a direction is the sum of the density of each light weighted by
+/// the probability of picking it.
+pub struct LightList {
+    lights: Vec<Arc<dyn Hitable>>,
+    powers: Vec<f32>,
+    cdf: Vec<f32>,
+    selection: LightSelection,
+}
+
+impl LightList {
+    pub fn new(selection: LightSelection) -> LightList {
+        LightList { lights: Vec::new(),
+                    powers: Vec::new(),
+                    cdf: Vec::new(),
+                    selection }
+    }
+
+    /// Add a light that emits the given power
+    ///
+    /// The power only needs to be correct relative to the other lights.
+    /// It is usually the luminance of the emitted color times the area
+    /// of the light.
+    pub fn add<H: Hitable + 'static>(&mut self, light: H, power: f32) {
+        self.add_box(Arc::new(light), power);
+    }
+
+    pub fn add_box(&mut self, light: Arc<dyn Hitable>, power: f32) {
+        self.lights.push(light);
+        self.powers.push(power.max(0.0));
+        self.update_cdf();
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    pub fn selection(&self) -> LightSelection {
+        self.selection
+    }
+
+    pub fn set_selection(&mut self, selection: LightSelection) {
+        self.selection = selection;
+        self.update_cdf();
+    }
+
+    /// The probability of picking the light at the given index
+    pub fn probability(&self, index: usize) -> f32 {
+        if index == 0 {
+            self.cdf[0]
+        } else {
+            self.cdf[index] - self.cdf[index - 1]
+        }
+    }
+
+    /// Pick a light given a uniform random number in [0, 1)
+    pub fn select(&self, u: f32) -> usize {
+        let index = self.cdf.iter().position(|&c| u < c).unwrap_or(self.cdf.len() - 1);
+
+        // skip lights that can never be picked when u lands exactly on a boundary
+        (index..self.cdf.len()).find(|&i| self.probability(i) > 0.0)
+                               .unwrap_or(index)
+    }
+
+    fn update_cdf(&mut self) {
+        let total: f32 = self.powers.iter().sum();
+
+        let weights: Vec<f32> = match self.selection {
+            LightSelection::Power if total > 0.0 => self.powers.iter().map(|p| p / total).collect(),
+            _ => vec![1.0 / self.lights.len() as f32; self.lights.len()],
+        };
+
+        let mut accumulated = 0.0;
+        self.cdf = weights.iter()
+                          .map(|w| {
+                              accumulated += w;
+                              accumulated
+                          })
+                          .collect();
+
+        if let Some(last) = self.cdf.last_mut() {
+            *last = 1.0;
+        }
+    }
+}
+
+impl Hitable for LightList {
+    fn hit(&self, ray: &Ray, position_min: f32, position_max: f32) -> Option<HitRecord> {
+        let mut closest_so_far = position_max;
+        let mut record = None;
+
+        for light in &self.lights {
+            if let Some(hit) = light.hit(ray, position_min, closest_so_far) {
+                closest_so_far = hit.parameter;
+                record = Some(hit);
+            }
+        }
+
+        record
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        self.lights
+            .iter()
+            .filter_map(|light| light.bounding_box(t0, t1))
+            .fold(None, |accumulated: Option<AABB>, bbox| match accumulated {
+                Some(accumulated) => Some(accumulated.surrounding_box(&bbox)),
+                None => Some(bbox),
+            })
+    }
+
+    /// The density of sampling direction from origin with pdf_random
+    ///
+    /// Lights only count towards the density in the directions
+    /// in which they emit towards origin.
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        self.lights
+            .iter()
+            .enumerate()
+            .filter(|(_, light)| emits_towards(light.as_ref(), origin, direction, time))
+            .map(|(i, light)| self.probability(i) * light.pdf_value(origin, direction, time))
+            .sum()
+    }
+
+    /// Pick a light and sample a direction from origin towards it
+    ///
+    /// None is returned when the sampled point of the light doesn't emit
+    /// towards origin, such as the back of a one-sided light.
+    fn pdf_random(&self, origin: Vec3, time: f32, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        if self.lights.is_empty() {
+            return None;
+        }
+
+        let light = &self.lights[self.select(sampler.get_1d())];
+        light.pdf_random(origin, time, sampler)
+             .filter(|&direction| emits_towards(light.as_ref(), origin, direction, time))
+    }
+}
+
+/// Determine if the light emits back along direction towards origin
+///
+/// The light is hit with its own orientation, so lights wrapped
+/// in FlipNormals emit from the flipped side.
+fn emits_towards(light: &dyn Hitable, origin: Vec3, direction: Vec3, time: f32) -> bool {
+    let ray = Ray::new(origin, direction, time);
+    match light.hit(&ray, 0.001, f32::MAX) {
+        Some(hit) => hit.material.emitted(&ray, &hit).max_element() > 0.0,
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use materials::Empty;
+    use plane::{Axis, Plane};
+
+    #[test]
+    fn test_light_selection() {
+        let mut lights = LightList::new(LightSelection::Power);
+        lights.add(Plane::new(Axis::XZ, 0.0, 1.0, 0.0, 1.0, 1.0, Empty::new()), 1.0);
+        lights.add(Plane::new(Axis::XZ, 0.0, 1.0, 0.0, 1.0, 2.0, Empty::new()), 3.0);
+
+        assert!((lights.probability(0) - 0.25).abs() < 1e-6);
+        assert!((lights.probability(1) - 0.75).abs() < 1e-6);
+        assert_eq!(lights.select(0.2), 0);
+        assert_eq!(lights.select(0.3), 1);
+
+        lights.set_selection(LightSelection::Uniform);
+
+        assert!((lights.probability(1) - 0.5).abs() < 1e-6);
+        assert_eq!(lights.select(0.3), 0);
+    }
+}
@@ -1,72 +1,66 @@
-#![allow(dead_code)]
-
 extern crate chrono;
-extern crate glam;
-extern crate image;
-extern crate image2;
-extern crate nalgebra;
 extern crate pbr;
-extern crate rand;
-extern crate rand_distr;
-extern crate rayon;
-extern crate tobj;
-
-mod aabb;
-mod basis;
-mod bvh;
-mod camera;
-mod denoise;
-mod hitable;
-mod integrator;
-mod materials;
-mod pdf;
-mod plane;
-mod post;
-mod ray;
-mod rectangle;
-mod sampling;
-mod scene;
-mod sphere;
-mod texture;
-mod tone;
-mod transformations;
-mod triangle;
-mod utils;
-mod volume;
-mod world;
+extern crate renderama;
+
+mod cli;
 
 use std::env;
-use std::f32;
+use std::process;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
 
 use chrono::{DateTime, Local};
-use glam::Vec3;
-use image2::{ImageBuf, Rgb};
 use pbr::ProgressBar;
-use rand::thread_rng;
-use rayon::prelude::*;
 
-#[cfg(feature = "denoise")]
-use denoise::denoise;
+use renderama::{gltf_file, scene, scene_file, utils, Renderer, Settings};
+
+use cli::CliError;
 
 fn main() {
-    let rendering_time = Instant::now();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match cli::parse(&args) {
+        Ok(options) => options,
+        Err(CliError::Help) => {
+            println!("{}", cli::usage());
+            return;
+        }
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, cli::usage());
+            process::exit(1);
+        }
+    };
 
-    let (width, height): (usize, usize) = (2048, 2048);
-    let args: Vec<String> = env::args().collect();
-    let samples: u32 = args[1].parse().unwrap();
-    let bounces: u32 = 10;
+    let rendering_time = Instant::now();
 
-    let (name, camera, world, light_source) = scene::cornell_box_scene(width, height);
+    let mut settings = Settings::new(options.width, options.height, options.samples, options.bounces);
+    settings.sampler = options.sampler;
+    settings.seed = options.seed;
+    let (width, height) = (settings.width, settings.height);
+
+    let loaded = if options.scene.ends_with(".toml") {
+        Some(scene_file::load(&options.scene, width, height))
+    } else if options.scene.ends_with(".gltf") || options.scene.ends_with(".glb") {
+        Some(gltf_file::load(&options.scene, width, height))
+    } else {
+        None
+    };
+
+    let scene = match loaded {
+        Some(Ok(scene)) => scene,
+        Some(Err(error)) => {
+            eprintln!("error: {}: {}", options.scene, error);
+            process::exit(1);
+        }
+        None => scene::from_name(&options.scene, width, height, settings.seed).unwrap(),
+    };
 
     let render_start_time: DateTime<Local> = Local::now();
     println!("[{}] Rendering '{}' scene with {} samples at {} x {} dimensions...",
              render_start_time.format("%H:%M:%S"),
-             name,
-             samples,
+             scene.name,
+             settings.samples,
              width,
              height);
 
@@ -75,7 +69,7 @@ fn main() {
 
     let atomic_counter = Arc::new(AtomicU64::new(0));
     let cloned_counter = atomic_counter.clone();
-    let seconds = (samples as f32).log2();
+    let seconds = (settings.samples as f32).log2();
 
     thread::spawn(move || {
         while cloned_counter.load(Ordering::SeqCst) < (width * height) as u64 {
@@ -85,61 +79,51 @@ fn main() {
         }
     });
 
-    let mut pixels = vec![0.0f32; 3 * width * height];
-    pixels.par_chunks_mut(3).enumerate().for_each(|(i, pixel)| {
-        let mut color = Vec3::zero();
-
-        let x = i % width;
-        let y = height - (i / width) - 1;
-
-        let mut rng = thread_rng();
-
-        (0..samples).for_each(|_| {
-            let u = (x as f32 + rand::random::<f32>()) / width as f32;
-            let v = (y as f32 + rand::random::<f32>()) / height as f32;
-            let ray = camera.get_ray(u, v, &mut rng);
-            color += utils::de_nan(&integrator::render_path_integrator(ray,
-                                                        &world,
-                                                        bounces,
-                                                        &light_source,
-                                                        camera.atmosphere,
-                                                        &mut rng));
-        });
-
-        color /= samples as f32;
-
-        pixel[0] = color.x();
-        pixel[1] = color.y();
-        pixel[2] = color.z();
-
-        atomic_counter.fetch_add(1, Ordering::SeqCst);
-    });
+    let film = Renderer::new(settings).with_progress(atomic_counter)
+                                      .render(&scene);
 
     let render_end_time: DateTime<Local> = Local::now();
-    println!("[{}] Finished rendering in {}. Render saved to render.hdr.",
+    println!("[{}] Finished rendering in {}. Render saved to {}.",
              render_end_time.format("%H:%M:%S"),
-             utils::format_time(rendering_time.elapsed()));
-
-    let buffer: ImageBuf<f32, Rgb> = ImageBuf::new_from(width, height, pixels.clone());
+             utils::format_time(rendering_time.elapsed()),
+             options.output);
 
-    image2::io::write("render.hdr", &buffer).unwrap();
+    if let Err(e) = film.write(&options.output, options.format) {
+        eprintln!("error: could not write {}: {}", options.output, e);
+        process::exit(1);
+    }
 
     #[cfg(feature = "denoise")]
     {
-        let denoising_time = Instant::now();
-        let denoise_start_time: DateTime<Local> = Local::now();
-        println!("[{}] Denoising image...",
-                 denoise_start_time.format("%H:%M:%S"));
-
-        let denoised_output = denoise(&pixels, width, height);
-
-        let denoise_end_time: DateTime<Local> = Local::now();
-        println!("[{}] Finished denoising in {}. Render saved to denoised_render.hdr.",
-                 denoise_end_time.format("%H:%M:%S"),
-                 utils::format_time(denoising_time.elapsed()));
-
-        let denoised_buffer: ImageBuf<f32, Rgb> = ImageBuf::new_from(width, height, denoised_output);
-
-        image2::io::write("denoised_render.hdr", &denoised_buffer).unwrap();
+        if options.denoise {
+            let denoised_path = denoised_path(&options.output);
+            let denoising_time = Instant::now();
+            let denoise_start_time: DateTime<Local> = Local::now();
+            println!("[{}] Denoising image...",
+                     denoise_start_time.format("%H:%M:%S"));
+
+            let denoised_film = film.denoise();
+
+            let denoise_end_time: DateTime<Local> = Local::now();
+            println!("[{}] Finished denoising in {}. Render saved to {}.",
+                     denoise_end_time.format("%H:%M:%S"),
+                     utils::format_time(denoising_time.elapsed()),
+                     denoised_path);
+
+            if let Err(e) = denoised_film.write(&denoised_path, options.format) {
+                eprintln!("error: could not write {}: {}", denoised_path, e);
+                process::exit(1);
+            }
+        }
     }
 }
+
+/// Prefix the file name of the output path with "denoised_"
+#[cfg(feature = "denoise")]
+fn denoised_path(output: &str) -> String {
+    let path = std::path::Path::new(output);
+    let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or(output);
+    path.with_file_name(format!("denoised_{}", file_name))
+        .to_string_lossy()
+        .into_owned()
+}
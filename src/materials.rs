@@ -2,14 +2,16 @@ use std::f32::consts::PI;
 use std::sync::Arc;
 
 use glam::Vec3;
-use rand::rngs::ThreadRng;
 
 use basis::OrthonormalBasis;
 use hitable::HitRecord;
 use integrator::pick_sphere_point;
+use microfacet::{self, fresnel_conductor, fresnel_dielectric, half_vector, TrowbridgeReitz};
 use pdf::PDF;
 use ray::Ray;
-use texture::Texture;
+use sampler::Sampler;
+use texture::{ConstantTexture, Texture};
+use utils::luminance;
 
 pub struct ScatterRecord<'a> {
     pub specular_ray: Ray,
@@ -36,7 +38,7 @@ pub trait Material: Send + Sync {
     fn scatter(&self,
                _ray: &Ray,
                _record: &HitRecord,
-               _rng: &mut ThreadRng)
+               _sampler: &mut dyn Sampler)
                -> Option<ScatterRecord> {
         None
     }
@@ -48,6 +50,25 @@ pub trait Material: Send + Sync {
     fn scattering_pdf(&self, _ray: &Ray, _record: &HitRecord, _scattered: &Ray) -> f32 {
         1.0
     }
+
+    /// The scattering_pdf of every color channel
+    ///
+    /// Materials that scatter the channels differently, like metals
+    /// that tint their reflections, override this instead.
+    fn scattering(&self, ray: &Ray, record: &HitRecord, scattered: &Ray) -> Vec3 {
+        Vec3::splat(self.scattering_pdf(ray, record, scattered))
+    }
+
+    /// The attenuation of the light a material scatters off its non-specular lobes
+    ///
+    /// The light scattered other than by specular reflection or refraction
+    /// is the albedo times scattering, whichever lobe the scatter records
+    /// pick at random. Materials that are layered under others, like the
+    /// base of Coated, must return it when they have non-specular lobes.
+    /// Materials that only scatter specularly or do not scatter return None.
+    fn albedo(&self, _ray: &Ray, _record: &HitRecord) -> Option<Vec3> {
+        None
+    }
 }
 
 #[derive(Clone)]
@@ -59,11 +80,24 @@ impl Empty {
     }
 }
 impl Material for Empty {
-    fn scatter(&self, _ray: &Ray, _hit: &HitRecord, _rng: &mut ThreadRng) -> Option<ScatterRecord> {
+    fn scatter(&self,
+               _ray: &Ray,
+               _hit: &HitRecord,
+               _sampler: &mut dyn Sampler)
+               -> Option<ScatterRecord> {
         None
     }
 }
 
+/// Tint a color by the vertex color of the hit, if the mesh that was hit has colors
+fn vertex_tinted(color: Vec3, record: &HitRecord) -> Vec3 {
+    match record.vertex_color {
+        Some(vertex_color) => color * vertex_color,
+        None => color,
+    }
+}
+
+/// Diffuse scatters light in every direction by its albedo, tinted by vertex colors
 #[derive(Clone)]
 pub struct Diffuse {
     pub albedo: Arc<dyn Texture>,
@@ -77,8 +111,10 @@ impl Diffuse {
     /// albedo is a Vec3 of the RGB values assigned to the material
     /// where each value is a float between 0.0 and 1.0.
     pub fn new<T: Texture + 'static>(albedo: T, sigma: f32) -> Diffuse {
-        let albedo = Arc::new(albedo);
+        Diffuse::from_box(Arc::new(albedo), sigma)
+    }
 
+    pub fn from_box(albedo: Arc<dyn Texture>, sigma: f32) -> Diffuse {
         let constant = PI + sigma * (3.0 * PI - 4.0) / 6.0;
         let alpha = 1.0 / constant;
         let beta = sigma / constant;
@@ -94,14 +130,18 @@ impl Material for Diffuse {
     fn scatter(&self,
                ray: &Ray,
                record: &HitRecord,
-               _rng: &mut ThreadRng)
+               _sampler: &mut dyn Sampler)
                -> Option<ScatterRecord> {
         let scattered = Ray::new(record.point, ray.direction, ray.time);
-        let attenuation = self.albedo.value(record.u, record.v, &record.point);
+        let attenuation = vertex_tinted(self.albedo.value(record.u, record.v, &record.point), record);
         let pdf = PDF::CosinePDF { uvw: OrthonormalBasis::new(&record.shading_normal) };
         Some(ScatterRecord::new(scattered, attenuation, pdf, false))
     }
 
+    fn albedo(&self, _ray: &Ray, record: &HitRecord) -> Option<Vec3> {
+        Some(vertex_tinted(self.albedo.value(record.u, record.v, &record.point), record))
+    }
+
     /// Reflect light according to the Oren-Nayar model
     ///
     /// This method uses the improved Oren-Nayar model as implemented in Cycles:
@@ -119,6 +159,11 @@ impl Material for Diffuse {
         let nv = n.dot(v).max(0.0);
         let lv = l.dot(v);
 
+        // light sampled below the surface isn't reflected
+        if nl == 0.0 {
+            return 0.0;
+        }
+
         let s = lv - nl * nv;
         let t = if s > 0.0 { nl.max(nv) } else { 1.0 };
 
@@ -171,6 +216,31 @@ fn schlick(cosine: f32, reference_index: f32) -> f32 {
     r0 + (1.0 - r0) * (1.0 - cosine).powf(5.0)
 }
 
+/// Find the absorption coefficient of a medium that tints white light to color over distance
+///
+/// Colors are easier to choose than coefficients. The medium is
+/// lighter than color where it is thinner and darker where it is thicker.
+pub fn absorption_at_distance(color: Vec3, distance: f32) -> Vec3 {
+    let coefficient = |channel: f32| -channel.max(1e-6).ln() / distance;
+    Vec3::new(coefficient(color.x()), coefficient(color.y()), coefficient(color.z()))
+}
+
+/// The fraction of light left after a ray leaves an absorbing medium
+///
+/// A ray that leaves the medium, judged by the geometric normal, has
+/// travelled through it since its origin, so the light along it is
+/// attenuated following the Beer-Lambert law by the length of the ray.
+fn transmittance(absorption: Vec3, ray: &Ray, record: &HitRecord) -> Vec3 {
+    if absorption == Vec3::zero() || ray.direction.dot(record.geometric_normal) <= 0.0 {
+        return Vec3::one();
+    }
+
+    let distance = record.parameter * ray.direction.length();
+    Vec3::new((-absorption.x() * distance).exp(),
+              (-absorption.y() * distance).exp(),
+              (-absorption.z() * distance).exp())
+}
+
 #[derive(Clone)]
 pub struct Reflective {
     pub albedo: Vec3,
@@ -199,19 +269,289 @@ impl Material for Reflective {
     /// factor is also added in to account for the reflection fuzz due to
     /// the size of the sphere. The target minus the record.point is used
     /// to determine the ray that is being reflected from the surface of the material.
-    fn scatter(&self, ray: &Ray, record: &HitRecord, rng: &mut ThreadRng) -> Option<ScatterRecord> {
+    fn scatter(&self,
+               ray: &Ray,
+               record: &HitRecord,
+               sampler: &mut dyn Sampler)
+               -> Option<ScatterRecord> {
         let reflected: Vec3 = reflect(ray.direction, record.shading_normal);
         let specular_ray = Ray::new(record.point,
-                                    reflected + self.fuzz * pick_sphere_point(rng),
+                                    reflected + self.fuzz * pick_sphere_point(sampler),
                                     ray.time);
         let pdf = PDF::CosinePDF { uvw: OrthonormalBasis::new(&record.shading_normal) };
         Some(ScatterRecord::new(specular_ray, self.albedo, pdf, true))
     }
 }
 
+/// Conductor is a rough metal that reflects light off GGX distributed microfacets
+///
+/// The color of the metal comes from the Fresnel reflectance of its
+/// complex index of refraction eta + ik. A roughness of 0 makes a
+/// perfect mirror and a roughness of 1 a dull, almost diffuse metal.
+/// Anisotropic metals, like brushed ones, stretch their highlights
+/// along the tangent of the surface.
+#[derive(Clone)]
+pub struct Conductor {
+    pub eta: Vec3,
+    pub k: Vec3,
+    pub distribution: TrowbridgeReitz,
+}
+
+impl Conductor {
+    pub fn new(eta: Vec3, k: Vec3, roughness: f32) -> Conductor {
+        Conductor { eta,
+                    k,
+                    distribution: TrowbridgeReitz::from_roughness(roughness) }
+    }
+
+    pub fn gold(roughness: f32) -> Conductor {
+        Conductor::new(Vec3::new(0.143119, 0.374957, 1.44248),
+                       Vec3::new(3.98316, 2.38572, 1.60322),
+                       roughness)
+    }
+
+    pub fn copper(roughness: f32) -> Conductor {
+        Conductor::new(Vec3::new(0.200438, 0.924033, 1.10221),
+                       Vec3::new(3.91295, 2.45285, 2.14219),
+                       roughness)
+    }
+
+    pub fn aluminium(roughness: f32) -> Conductor {
+        Conductor::new(Vec3::new(1.65746, 0.880369, 0.521229),
+                       Vec3::new(9.22387, 6.26952, 4.837),
+                       roughness)
+    }
+
+    pub fn silver(roughness: f32) -> Conductor {
+        Conductor::new(Vec3::new(0.155265, 0.116723, 0.138342),
+                       Vec3::new(4.82835, 3.12225, 2.14696),
+                       roughness)
+    }
+
+    /// Stretch the microfacets by an anisotropy from 0 to 1, the way Principled does
+    ///
+    /// The widths of the distribution along and across the tangent keep
+    /// their geometric mean, so the metal looks as rough overall.
+    pub fn with_anisotropy(mut self, anisotropic: f32) -> Conductor {
+        let aspect = (1.0 - 0.9 * anisotropic.clamp(0.0, 1.0)).sqrt();
+        let alpha = (self.distribution.alpha_x * self.distribution.alpha_y).sqrt();
+        self.distribution = TrowbridgeReitz::anisotropic(alpha / aspect, alpha * aspect);
+        self
+    }
+}
+
+/// The shading normal turned towards the side of the surface the ray comes from
+fn facing_normal(ray: &Ray, record: &HitRecord) -> Vec3 {
+    if ray.direction.dot(record.shading_normal) > 0.0 {
+        -record.shading_normal
+    } else {
+        record.shading_normal
+    }
+}
+
+impl Material for Conductor {
+    /// Sample the microfacets visible from the ray, or reflect it if the metal is smooth
+    fn scatter(&self,
+               ray: &Ray,
+               record: &HitRecord,
+               _sampler: &mut dyn Sampler)
+               -> Option<ScatterRecord> {
+        let normal = facing_normal(ray, record);
+        let uvw = tangent_frame(&normal, record);
+        let wo = uvw.to_local(&-ray.direction.normalize());
+
+        if self.distribution.is_smooth() {
+            let reflected = Ray::new(record.point, reflect(ray.direction, normal), ray.time);
+            let attenuation = fresnel_conductor(wo.z(), self.eta, self.k);
+            let pdf = PDF::CosinePDF { uvw };
+            return Some(ScatterRecord::new(reflected, attenuation, pdf, true));
+        }
+
+        let scattered = Ray::new(record.point, ray.direction, ray.time);
+        let pdf = PDF::GGXPDF { uvw,
+                                wo,
+                                distribution: self.distribution };
+        Some(ScatterRecord::new(scattered, Vec3::one(), pdf, false))
+    }
+
+    fn albedo(&self, _ray: &Ray, _record: &HitRecord) -> Option<Vec3> {
+        if self.distribution.is_smooth() {
+            None
+        } else {
+            Some(Vec3::one())
+        }
+    }
+
+    /// Reflect light by the microfacets that face halfway between the two directions
+    ///
+    /// The reflected light is F * D * G / (4 * cos_o), the Cook-Torrance
+    /// BRDF multiplied by the cosine of the scattered direction.
+    fn scattering(&self, ray: &Ray, record: &HitRecord, scattered: &Ray) -> Vec3 {
+        let uvw = tangent_frame(&facing_normal(ray, record), record);
+        let wo = uvw.to_local(&-ray.direction.normalize());
+        let wi = uvw.to_local(&scattered.direction.normalize());
+
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Vec3::zero();
+        }
+
+        let h = (wo + wi).normalize();
+        let fresnel = fresnel_conductor(wo.dot(h), self.eta, self.k);
+        fresnel * self.distribution.d(h) * self.distribution.g(wo, wi) / (4.0 * wo.z())
+    }
+}
+
+/// RoughDielectric is frosted glass that reflects and refracts light through GGX microfacets
+///
+/// The light that passes through the surface is tinted, so that a
+/// closed object looks like colored glass. A roughness of 0 makes
+/// clear glass.
+///
+/// Bruce Walter, Stephen R. Marschner, Hongsong Li, Kenneth E. Torrance:
+/// Microfacet Models for Refraction through Rough Surfaces, EGSR 2007
+#[derive(Clone)]
+pub struct RoughDielectric {
+    pub refractive_index: f32,
+    pub distribution: TrowbridgeReitz,
+    pub tint: Arc<dyn Texture>,
+    pub absorption: Vec3,
+}
+
+impl RoughDielectric {
+    pub fn new<T: Texture + 'static>(index: f32, roughness: f32, tint: T) -> RoughDielectric {
+        RoughDielectric::from_box(index, roughness, Arc::new(tint))
+    }
+
+    pub fn from_box(index: f32, roughness: f32, tint: Arc<dyn Texture>) -> RoughDielectric {
+        RoughDielectric { refractive_index: index,
+                          distribution: TrowbridgeReitz::from_roughness(roughness),
+                          tint,
+                          absorption: Vec3::zero() }
+    }
+
+    /// Absorb light inside the glass by the coefficient of each color channel per unit length
+    pub fn with_absorption(mut self, absorption: Vec3) -> RoughDielectric {
+        self.absorption = absorption;
+        self
+    }
+}
+
+/// Find the frame of the side of the surface the ray comes from and the relative index
+///
+/// The geometric normal decides whether the ray enters or leaves the
+/// object, since a shading normal may lean away from the ray. The
+/// geometric normal is also used for the frame when the shading
+/// normal faces away from the ray.
+fn dielectric_frame(ray: &Ray, record: &HitRecord, index: f32) -> (OrthonormalBasis, f32) {
+    let entering = ray.direction.dot(record.geometric_normal) < 0.0;
+    let (geometric_normal, shading_normal, eta) = if entering {
+        (record.geometric_normal, record.shading_normal, index)
+    } else {
+        (-record.geometric_normal, -record.shading_normal, 1.0 / index)
+    };
+
+    let normal = if ray.direction.dot(shading_normal) < 0.0 { shading_normal } else { geometric_normal };
+    (tangent_frame(&normal, record), eta)
+}
+
+/// Find the frame around the normal whose first axis follows the tangent of the hit
+///
+/// Anisotropic microfacets are stretched along the first axis, so their
+/// highlights follow the texture coordinates of the surface. Surfaces
+/// without a tangent fall back to a frame built from the normal alone, in
+/// which the highlights point in an arbitrary direction that may jump
+/// between neighbouring normals.
+fn tangent_frame(normal: &Vec3, record: &HitRecord) -> OrthonormalBasis {
+    record.tangent
+          .and_then(|tangent| OrthonormalBasis::from_tangent(normal, &tangent))
+          .unwrap_or_else(|| OrthonormalBasis::new(normal))
+}
+
+/// Reflect or refract light by the microfacets that turn wo into wi
+///
+/// Light that crosses into a denser medium is compressed into a smaller
+/// solid angle, so the radiance carried along the path is divided by
+/// the square of the relative index of refraction.
+fn rough_dielectric(distribution: &TrowbridgeReitz, wo: Vec3, wi: Vec3, eta: f32) -> f32 {
+    let h = match half_vector(wo, wi, eta) {
+        Some(h) if wo.z() > 0.0 => h,
+        _ => return 0.0,
+    };
+
+    let fresnel = fresnel_dielectric(wo.dot(h), eta);
+    let microfacets = distribution.d(h) * distribution.g(wo, wi);
+
+    if wi.z() > 0.0 {
+        return fresnel * microfacets / (4.0 * wo.z());
+    }
+
+    let denominator = wo.dot(h) + eta * wi.dot(h);
+    (1.0 - fresnel) * microfacets * (wo.dot(h) * wi.dot(h)).abs() / (wo.z() * denominator * denominator)
+}
+
+impl Material for RoughDielectric {
+    /// Sample the microfacets visible from the ray, or reflect or refract it if the glass is clear
+    fn scatter(&self,
+               ray: &Ray,
+               record: &HitRecord,
+               sampler: &mut dyn Sampler)
+               -> Option<ScatterRecord> {
+        let (uvw, eta) = dielectric_frame(ray, record, self.refractive_index);
+        let wo = uvw.to_local(&-ray.direction.normalize());
+        let absorbed = transmittance(self.absorption, ray, record);
+
+        if self.distribution.is_smooth() {
+            let normal = Vec3::new(0.0, 0.0, 1.0);
+            let refracted = if sampler.get_1d() < fresnel_dielectric(wo.z(), eta) {
+                None
+            } else {
+                microfacet::refract(wo, normal, eta)
+            };
+
+            let (wi, attenuation) = match refracted {
+                Some(wi) => (wi, self.tint.value(record.u, record.v, &record.point) / (eta * eta)),
+                None => (2.0 * wo.z() * normal - wo, Vec3::one()),
+            };
+            let specular_ray = Ray::new(record.point, uvw.local(&wi), ray.time);
+            let pdf = PDF::CosinePDF { uvw };
+            return Some(ScatterRecord::new(specular_ray, absorbed * attenuation, pdf, true));
+        }
+
+        let scattered = Ray::new(record.point, ray.direction, ray.time);
+        let pdf = PDF::DielectricPDF { uvw,
+                                       wo,
+                                       eta,
+                                       distribution: self.distribution };
+        Some(ScatterRecord::new(scattered, absorbed, pdf, false))
+    }
+
+    fn albedo(&self, ray: &Ray, record: &HitRecord) -> Option<Vec3> {
+        if self.distribution.is_smooth() {
+            None
+        } else {
+            Some(transmittance(self.absorption, ray, record))
+        }
+    }
+
+    /// Reflect light white and tint the light refracted through the glass
+    fn scattering(&self, ray: &Ray, record: &HitRecord, scattered: &Ray) -> Vec3 {
+        let (uvw, eta) = dielectric_frame(ray, record, self.refractive_index);
+        let wo = uvw.to_local(&-ray.direction.normalize());
+        let wi = uvw.to_local(&scattered.direction.normalize());
+
+        let scattering = rough_dielectric(&self.distribution, wo, wi, eta);
+        if wi.z() > 0.0 {
+            Vec3::splat(scattering)
+        } else {
+            self.tint.value(record.u, record.v, &record.point) * scattering
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Refractive {
     pub refractive_index: f32,
+    pub absorption: Vec3,
 }
 
 impl Refractive {
@@ -223,7 +563,14 @@ impl Refractive {
     /// fuzz accounts for the fuzziness of the reflections due to the size of the sphere.
     /// Generally, the larger the sphere, the fuzzier the reflections will be.
     pub fn new(index: f32) -> Refractive {
-        Refractive { refractive_index: index }
+        Refractive { refractive_index: index,
+                     absorption: Vec3::zero() }
+    }
+
+    /// Absorb light inside the material by the coefficient of each color channel per unit length
+    pub fn with_absorption(mut self, absorption: Vec3) -> Refractive {
+        self.absorption = absorption;
+        self
     }
 }
 
@@ -243,7 +590,7 @@ impl Material for Refractive {
     fn scatter(&self,
                ray: &Ray,
                record: &HitRecord,
-               _rng: &mut ThreadRng)
+               sampler: &mut dyn Sampler)
                -> Option<ScatterRecord> {
         let reflected: Vec3 = reflect(ray.direction, record.shading_normal);
         let incident: f32 = ray.direction.dot(record.shading_normal);
@@ -265,10 +612,10 @@ impl Material for Refractive {
             None => 1.0,
         };
 
-        let attenuation = Vec3::new(1.0, 1.0, 1.0);
+        let attenuation = transmittance(self.absorption, ray, record);
         let pdf = PDF::CosinePDF { uvw: OrthonormalBasis::new(&record.shading_normal) };
 
-        if rand::random::<f32>() < reflect_probability {
+        if sampler.get_1d() < reflect_probability {
             let specular_ray = Ray::new(record.point, reflected, ray.time);
             Some(ScatterRecord::new(specular_ray, attenuation, pdf, true))
         } else {
@@ -278,15 +625,29 @@ impl Material for Refractive {
     }
 }
 
+/// Light emits from the side its surface normal faces, or from both sides
 #[derive(Clone)]
 pub struct Light {
     pub emit: Arc<dyn Texture>,
+    pub two_sided: bool,
 }
 
 impl Light {
     pub fn new<T: Texture + 'static>(emit: T) -> Light {
         let emit = Arc::new(emit);
-        Light { emit: emit }
+        Light { emit: emit,
+                two_sided: false }
+    }
+
+    pub fn from_box(emit: Arc<dyn Texture>) -> Light {
+        Light { emit,
+                two_sided: false }
+    }
+
+    /// Create a light that emits from the front and the back of its surface
+    pub fn two_sided<T: Texture + 'static>(emit: T) -> Light {
+        Light { emit: Arc::new(emit),
+                two_sided: true }
     }
 }
 
@@ -294,13 +655,13 @@ impl Material for Light {
     fn scatter(&self,
                _ray: &Ray,
                _record: &HitRecord,
-               _rng: &mut ThreadRng)
+               _sampler: &mut dyn Sampler)
                -> Option<ScatterRecord> {
         None
     }
 
     fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Vec3 {
-        if hit.shading_normal.dot(ray.direction) < 0.0 {
+        if self.two_sided || hit.shading_normal.dot(ray.direction) < 0.0 {
             self.emit.value(hit.u, hit.v, &hit.point)
         } else {
             Vec3::zero()
@@ -318,13 +679,610 @@ impl Isotropic {
         let albedo = Arc::new(albedo);
         Isotropic { albedo }
     }
+
+    pub fn from_box(albedo: Arc<dyn Texture>) -> Isotropic {
+        Isotropic { albedo }
+    }
 }
 
 impl Material for Isotropic {
-    fn scatter(&self, ray: &Ray, record: &HitRecord, rng: &mut ThreadRng) -> Option<ScatterRecord> {
-        let scattered = Ray::new(record.point, pick_sphere_point(rng), ray.time);
+    fn scatter(&self,
+               ray: &Ray,
+               record: &HitRecord,
+               sampler: &mut dyn Sampler)
+               -> Option<ScatterRecord> {
+        let scattered = Ray::new(record.point, pick_sphere_point(sampler), ray.time);
         let attenuation = self.albedo.value(record.u, record.v, &record.point);
         let pdf = PDF::CosinePDF { uvw: OrthonormalBasis::new(&record.shading_normal) };
         Some(ScatterRecord::new(scattered, attenuation, pdf, true))
     }
 }
+
+/// The width of the microfacet distribution of the clearcoat of principled materials
+const CLEARCOAT_ALPHA: f32 = 0.05;
+
+/// Principled blends the lobes of the Disney BSDF by a handful of intuitive parameters
+///
+/// The base color is the albedo of the diffuse lobe and the color of metals,
+/// whose share is set by metallic. specular sets the reflectance of the
+/// dielectric part, 0.5 being glass with an index of refraction of 1.5, and
+/// specular_tint tints its reflections towards the base color. roughness and
+/// anisotropic widen the highlights and stretch them along the tangent of the
+/// surface, where it has one, sheen adds the bright rim of
+/// cloth, clearcoat a thin layer of varnish and transmission turns the
+/// dielectric part into rough glass of the base color. Every parameter is a
+/// texture, of which the scalar parameters read the first channel, and the
+/// base color is tinted by the vertex colors of meshes. The other parameters
+/// are set through the fields.
+///
+/// Brent Burley: Physically-Based Shading at Disney, SIGGRAPH 2012 Course
+///
+/// Brent Burley: Extending the Disney BRDF to a BSDF with Integrated
+/// Subsurface Scattering, SIGGRAPH 2015 Course
+#[derive(Clone)]
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    pub specular: Arc<dyn Texture>,
+    pub specular_tint: Arc<dyn Texture>,
+    pub anisotropic: Arc<dyn Texture>,
+    pub sheen: Arc<dyn Texture>,
+    pub clearcoat: Arc<dyn Texture>,
+    pub transmission: Arc<dyn Texture>,
+    pub emission: Arc<dyn Texture>,
+}
+
+impl Principled {
+    pub fn new<T: Texture + 'static>(base_color: T) -> Principled {
+        Principled::from_box(Arc::new(base_color))
+    }
+
+    /// Create an opaque, plastic-like surface of the base color
+    ///
+    /// Its roughness and specular are 0.5 and every other parameter is 0.
+    pub fn from_box(base_color: Arc<dyn Texture>) -> Principled {
+        let constant = |value: f32| -> Arc<dyn Texture> {
+            Arc::new(ConstantTexture::new(value, value, value))
+        };
+
+        Principled { base_color,
+                     metallic: constant(0.0),
+                     roughness: constant(0.5),
+                     specular: constant(0.5),
+                     specular_tint: constant(0.0),
+                     anisotropic: constant(0.0),
+                     sheen: constant(0.0),
+                     clearcoat: constant(0.0),
+                     transmission: constant(0.0),
+                     emission: constant(0.0) }
+    }
+
+    /// Look up the parameters at the hit and weight the lobes by them
+    fn lobes(&self, ray: &Ray, record: &HitRecord) -> Lobes {
+        let color = |texture: &Arc<dyn Texture>| texture.value(record.u, record.v, &record.point);
+        let scalar = |texture: &Arc<dyn Texture>| color(texture).x().clamp(0.0, 1.0);
+
+        let base_color = vertex_tinted(color(&self.base_color), record);
+        let metallic = scalar(&self.metallic);
+        let roughness = scalar(&self.roughness);
+        let specular = scalar(&self.specular);
+
+        // the index of refraction whose reflectance at normal incidence is 0.08 * specular
+        let reflectance = (0.08 * specular).sqrt().min(0.999);
+        let (uvw, eta) = dielectric_frame(ray, record, (1.0 + reflectance) / (1.0 - reflectance));
+
+        let luminance = luminance(&base_color);
+        let tint = if luminance > 0.0 { base_color / luminance } else { Vec3::one() };
+        let dielectric = 0.08 * specular * (Vec3::one() + scalar(&self.specular_tint) * (tint - Vec3::one()));
+
+        let aspect = (1.0 - 0.9 * scalar(&self.anisotropic)).sqrt();
+        let alpha = roughness * roughness;
+
+        Lobes { wo: uvw.to_local(&-ray.direction.normalize()),
+                uvw,
+                eta,
+                base_color,
+                roughness,
+                diffuse: (1.0 - metallic) * (1.0 - scalar(&self.transmission)),
+                transmission: (1.0 - metallic) * scalar(&self.transmission),
+                specular: dielectric + metallic * (base_color - dielectric),
+                sheen: scalar(&self.sheen) * (Vec3::one() + 0.5 * (tint - Vec3::one())),
+                clearcoat: scalar(&self.clearcoat),
+                distribution: TrowbridgeReitz::anisotropic((alpha / aspect).max(1e-3),
+                                                           (alpha * aspect).max(1e-3)),
+                clearcoat_distribution: TrowbridgeReitz::new(CLEARCOAT_ALPHA) }
+    }
+}
+
+/// The lobes of a principled material at a hit, in the frame of the side the ray comes from
+struct Lobes {
+    uvw: OrthonormalBasis,
+    wo: Vec3,
+    eta: f32,
+    base_color: Vec3,
+    roughness: f32,
+    /// The weight of the diffuse and sheen lobes
+    diffuse: f32,
+    /// The weight of the rough glass lobe, which leaves the rest to the specular lobe
+    transmission: f32,
+    /// The reflectance of the specular lobe at normal incidence
+    specular: Vec3,
+    sheen: Vec3,
+    clearcoat: f32,
+    distribution: TrowbridgeReitz,
+    clearcoat_distribution: TrowbridgeReitz,
+}
+
+/// The weight of Schlick's approximation of the Fresnel reflectance
+fn schlick_weight(cosine: f32) -> f32 {
+    (1.0 - cosine.clamp(0.0, 1.0)).powi(5)
+}
+
+impl Lobes {
+    /// The light scattered from wi towards wo by every lobe, multiplied by the cosine of wi
+    fn scattering(&self, wi: Vec3) -> Vec3 {
+        let wo = self.wo;
+        if wo.z() <= 0.0 {
+            return Vec3::zero();
+        }
+
+        let mut scattering = Vec3::zero();
+
+        if wi.z() > 0.0 {
+            let h = (wo + wi).normalize();
+            let cos_d = wi.dot(h);
+
+            // Burley's diffuse lobe brightens the rims of rough surfaces by retro-reflection
+            let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let diffuse = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z()))
+                          * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z()));
+            scattering += self.diffuse
+                          * (self.base_color * diffuse / PI + self.sheen * schlick_weight(cos_d))
+                          * wi.z();
+
+            let microfacets = self.distribution.d(h) * self.distribution.g(wo, wi) / (4.0 * wo.z());
+            let fresnel = self.specular + (Vec3::one() - self.specular) * schlick_weight(cos_d);
+            scattering += (1.0 - self.transmission) * fresnel * microfacets;
+
+            let coat = &self.clearcoat_distribution;
+            let fresnel = 0.04 + 0.96 * schlick_weight(cos_d);
+            scattering += Vec3::splat(0.25 * self.clearcoat * fresnel * coat.d(h) * coat.g(wo, wi)
+                                      / (4.0 * wo.z()));
+        }
+
+        if self.transmission > 0.0 {
+            let glass = self.transmission * rough_dielectric(&self.distribution, wo, wi, self.eta);
+            scattering += if wi.z() > 0.0 { Vec3::splat(glass) } else { self.base_color * glass };
+        }
+
+        scattering
+    }
+
+    /// Sample each lobe in proportion to its weight
+    fn pdf<'a>(&self) -> PDF<'a> {
+        let lobes = vec![(self.diffuse, PDF::CosinePDF { uvw: self.uvw.clone() }),
+                         (1.0 - self.transmission,
+                          PDF::GGXPDF { uvw: self.uvw.clone(),
+                                        wo: self.wo,
+                                        distribution: self.distribution }),
+                         (self.transmission,
+                          PDF::DielectricPDF { uvw: self.uvw.clone(),
+                                               wo: self.wo,
+                                               eta: self.eta,
+                                               distribution: self.distribution }),
+                         (0.25 * self.clearcoat,
+                          PDF::GGXPDF { uvw: self.uvw.clone(),
+                                        wo: self.wo,
+                                        distribution: self.clearcoat_distribution })];
+
+        let total: f32 = lobes.iter().map(|(weight, _)| weight).sum();
+        let pdfs = lobes.into_iter()
+                        .filter(|(weight, _)| *weight > 0.0)
+                        .map(|(weight, pdf)| (weight / total, pdf))
+                        .collect();
+        PDF::MixturePDF { pdfs }
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self,
+               ray: &Ray,
+               record: &HitRecord,
+               _sampler: &mut dyn Sampler)
+               -> Option<ScatterRecord> {
+        let lobes = self.lobes(ray, record);
+        let scattered = Ray::new(record.point, ray.direction, ray.time);
+        Some(ScatterRecord::new(scattered, Vec3::one(), lobes.pdf(), false))
+    }
+
+    fn albedo(&self, _ray: &Ray, _record: &HitRecord) -> Option<Vec3> {
+        Some(Vec3::one())
+    }
+
+    /// Emit light from the side the shading normal faces
+    fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Vec3 {
+        if hit.shading_normal.dot(ray.direction) < 0.0 {
+            self.emission.value(hit.u, hit.v, &hit.point)
+        } else {
+            Vec3::zero()
+        }
+    }
+
+    fn scattering(&self, ray: &Ray, record: &HitRecord, scattered: &Ray) -> Vec3 {
+        let lobes = self.lobes(ray, record);
+        lobes.scattering(lobes.uvw.to_local(&scattered.direction.normalize()))
+    }
+}
+
+/// The bounds of the probability of sampling a rough coat rather than its base
+///
+/// The coat is sampled by its Fresnel reflectance, which is only 4% at
+/// normal incidence and nearly 100% at grazing angles. The bounds keep
+/// both layers sampled at every angle, so that neither the highlights
+/// of the coat head-on nor the base at grazing angles become noise.
+const MIN_COAT_PROBABILITY: f32 = 0.25;
+const MAX_COAT_PROBABILITY: f32 = 0.9;
+
+/// Coated lays a clear dielectric coat, like varnish or the clearcoat of car paint, over a base material
+///
+/// The coat reflects light off GGX distributed microfacets by its Fresnel
+/// reflectance and lets the rest through to the base. Light reaches the base
+/// and leaves it again with the Fresnel transmittance of either direction, so
+/// the layers never reflect more light than arrives. A coat that absorbs light
+/// tints the base by the length of the refracted path through its thickness.
+/// The base is shaded with the directions outside the coat.
+#[derive(Clone)]
+pub struct Coated {
+    pub base: Arc<dyn Material>,
+    pub refractive_index: f32,
+    pub distribution: TrowbridgeReitz,
+    pub absorption: Vec3,
+    pub thickness: f32,
+}
+
+impl Coated {
+    pub fn new<M: Material + 'static>(base: M, refractive_index: f32, roughness: f32) -> Coated {
+        Coated::from_box(Arc::new(base), refractive_index, roughness)
+    }
+
+    pub fn from_box(base: Arc<dyn Material>, refractive_index: f32, roughness: f32) -> Coated {
+        Coated { base,
+                 refractive_index,
+                 distribution: TrowbridgeReitz::from_roughness(roughness),
+                 absorption: Vec3::zero(),
+                 thickness: 0.0 }
+    }
+
+    /// Absorb light inside a coat of the thickness by the absorption per unit length
+    pub fn with_absorption(mut self, absorption: Vec3, thickness: f32) -> Coated {
+        self.absorption = absorption;
+        self.thickness = thickness;
+        self
+    }
+
+    /// The fraction of light that crosses the coat at the cosine cos_theta to its normal
+    ///
+    /// Light is refracted into the coat, so it crosses the coat more
+    /// steeply than it arrives and is absorbed along a shorter path.
+    fn transmission(&self, cos_theta: f32) -> Vec3 {
+        let cos_theta = cos_theta.abs().min(1.0);
+        let fresnel = fresnel_dielectric(cos_theta, self.refractive_index);
+        let sin2_inside = (1.0 - cos_theta * cos_theta) / (self.refractive_index * self.refractive_index);
+        let distance = self.thickness / (1.0 - sin2_inside).sqrt();
+
+        (1.0 - fresnel)
+        * Vec3::new((-self.absorption.x() * distance).exp(),
+                    (-self.absorption.y() * distance).exp(),
+                    (-self.absorption.z() * distance).exp())
+    }
+}
+
+impl Material for Coated {
+    /// Reflect the ray off the coat or pass it through to the base
+    ///
+    /// A smooth coat reflects the ray with the probability of its Fresnel
+    /// reflectance. A rough coat is sampled together with a diffuse base and
+    /// chosen against a specular base, with the reflectance as its probability.
+    /// A base that picks between specular and diffuse lobes at random, like
+    /// a smooth coat over a diffuse material, has its diffuse lobes sampled
+    /// with the coat in place of the specular lobe the coat is chosen over.
+    fn scatter(&self,
+               ray: &Ray,
+               record: &HitRecord,
+               sampler: &mut dyn Sampler)
+               -> Option<ScatterRecord> {
+        let normal = facing_normal(ray, record);
+        let uvw = OrthonormalBasis::new(&normal);
+        let wo = uvw.to_local(&-ray.direction.normalize());
+        let fresnel = fresnel_dielectric(wo.z(), self.refractive_index);
+
+        if self.distribution.is_smooth() {
+            if sampler.get_1d() < fresnel {
+                let reflected = Ray::new(record.point, reflect(ray.direction, normal), ray.time);
+                return Some(ScatterRecord::new(reflected, Vec3::one(), PDF::CosinePDF { uvw }, true));
+            }
+
+            // the probability of passing the coat cancels its Fresnel transmittance
+            let mut base = self.base.scatter(ray, record, sampler)?;
+            base.attenuation *= self.transmission(wo.z()) / (1.0 - fresnel);
+            if base.specular {
+                base.attenuation *= self.transmission(base.specular_ray.direction.normalize().dot(normal));
+            }
+            return Some(base);
+        }
+
+        let coat = PDF::GGXPDF { uvw: uvw.clone(),
+                                 wo,
+                                 distribution: self.distribution };
+        let scattered = Ray::new(record.point, ray.direction, ray.time);
+        let probability = fresnel.clamp(MIN_COAT_PROBABILITY, MAX_COAT_PROBABILITY);
+
+        match self.base.scatter(ray, record, sampler) {
+            Some(mut base) if base.specular => {
+                if sampler.get_1d() < probability {
+                    let pdf = if self.base.albedo(ray, record).is_some() {
+                        PDF::MixturePDF { pdfs: vec![(probability, coat),
+                                                     (1.0 - probability, PDF::CosinePDF { uvw })] }
+                    } else {
+                        coat
+                    };
+                    return Some(ScatterRecord::new(scattered, Vec3::splat(1.0 / probability), pdf, false));
+                }
+
+                let cos_i = base.specular_ray.direction.normalize().dot(normal);
+                base.attenuation *= self.transmission(wo.z()) * self.transmission(cos_i) / (1.0 - probability);
+                Some(base)
+            }
+            Some(base) => {
+                let pdf = PDF::MixturePDF { pdfs: vec![(probability, coat), (1.0 - probability, base.pdf)] };
+                Some(ScatterRecord::new(scattered, Vec3::one(), pdf, false))
+            }
+            None => Some(ScatterRecord::new(scattered, Vec3::one(), coat, false)),
+        }
+    }
+
+    /// A smooth coat lets through the albedo of the base, and a rough coat leaves it to scattering
+    fn albedo(&self, ray: &Ray, record: &HitRecord) -> Option<Vec3> {
+        if self.distribution.is_smooth() {
+            let cos_o = -ray.direction.normalize().dot(facing_normal(ray, record));
+            self.base.albedo(ray, record).map(|albedo| albedo * self.transmission(cos_o))
+        } else {
+            Some(Vec3::one())
+        }
+    }
+
+    /// Let the light emitted by the base through the coat
+    fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Vec3 {
+        let emitted = self.base.emitted(ray, hit);
+        if emitted == Vec3::zero() {
+            return emitted;
+        }
+
+        emitted * self.transmission(ray.direction.normalize().dot(hit.shading_normal))
+    }
+
+    /// The reflection of a rough coat and the light the base scatters through it
+    ///
+    /// The attenuation of the base is left to the scatter record when the coat is smooth.
+    fn scattering(&self, ray: &Ray, record: &HitRecord, scattered: &Ray) -> Vec3 {
+        let normal = facing_normal(ray, record);
+        let uvw = OrthonormalBasis::new(&normal);
+        let wo = uvw.to_local(&-ray.direction.normalize());
+        let wi = uvw.to_local(&scattered.direction.normalize());
+
+        let base = self.transmission(wi.z()) * self.base.scattering(ray, record, scattered);
+        if self.distribution.is_smooth() {
+            return base;
+        }
+
+        let coat = if wi.z() > 0.0 {
+            rough_dielectric(&self.distribution, wo, wi, self.refractive_index)
+        } else {
+            0.0
+        };
+
+        match self.base.albedo(ray, record) {
+            Some(attenuation) => Vec3::splat(coat) + self.transmission(wo.z()) * attenuation * base,
+            None => Vec3::splat(coat),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sampler::IndependentSampler;
+    use texture::ConstantTexture;
+
+    /// Estimate the light a material scatters from a surface facing up, seen from wo
+    ///
+    /// The light reflected back to the side of wo and the light transmitted
+    /// through to the other side are estimated apart.
+    fn estimate_albedo(material: &dyn Material, wo: Vec3) -> (Vec3, Vec3) {
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let record = HitRecord::new(1.0, 0.0, 0.0, Vec3::zero(), normal, normal, Arc::new(Empty::new()));
+        let ray = Ray::new(wo, -wo, 0.0);
+        let mut sampler = IndependentSampler::new(1, 0);
+
+        let samples = 20000;
+        let (mut reflected, mut transmitted) = (Vec3::zero(), Vec3::zero());
+        for i in 0..samples {
+            sampler.start_pixel_sample(0, 0, i);
+            let scatter = material.scatter(&ray, &record, &mut sampler).unwrap();
+            let (direction, energy) = if scatter.specular {
+                (scatter.specular_ray.direction, scatter.attenuation)
+            } else {
+                let direction = match scatter.pdf.generate(&mut sampler) {
+                    Some(direction) => direction,
+                    None => continue,
+                };
+                let pdf = scatter.pdf.value(direction);
+                if pdf <= 0.0 {
+                    continue;
+                }
+                let scattered = Ray::new(record.point, direction, 0.0);
+                (direction, material.scattering(&ray, &record, &scattered) * scatter.attenuation / pdf)
+            };
+
+            if direction.y() * wo.y() > 0.0 {
+                reflected += energy;
+            } else {
+                transmitted += energy;
+            }
+        }
+
+        (reflected / samples as f32, transmitted / samples as f32)
+    }
+
+    #[test]
+    fn test_rough_dielectric_conserves_energy() {
+        // rays entering and leaving the glass, with the radiance scale of the crossing undone
+        for &(sign, eta) in &[(1.0, 1.5), (-1.0, 1.0 / 1.5)] {
+            for &roughness in &[0.1, 0.5] {
+                let glass = RoughDielectric::new(1.5, roughness, ConstantTexture::new(1.0, 1.0, 1.0));
+                let (reflected, transmitted) = estimate_albedo(&glass, Vec3::new(-0.6, 0.8 * sign, 0.0));
+                let energy = reflected.x() + transmitted.x() * eta * eta;
+
+                // only light that scatters between microfacets more than once is lost
+                assert!(energy < 1.01 && energy > 0.8);
+                if roughness < 0.2 {
+                    assert!(energy > 0.98);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_rough_conductor_conserves_energy() {
+        // a conductor that reflects everything loses only what scatters between microfacets
+        for &(roughness, anisotropic) in &[(0.1, 0.0), (0.5, 0.0), (0.5, 0.8)] {
+            let mirror = Conductor::new(Vec3::one(), Vec3::splat(1e4), roughness).with_anisotropy(anisotropic);
+            let (reflected, transmitted) = estimate_albedo(&mirror, Vec3::new(-0.6, 0.8, 0.0));
+            assert_eq!(transmitted, Vec3::zero());
+            assert!(reflected.x() < 1.01 && reflected.x() > 0.8);
+            if roughness < 0.2 {
+                assert!(reflected.x() > 0.98);
+            }
+        }
+    }
+
+    #[test]
+    fn test_smooth_conductor_reflects() {
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let record = HitRecord::new(1.0, 0.0, 0.0, Vec3::zero(), normal, normal, Arc::new(Empty::new()));
+        let ray = Ray::new(Vec3::new(-0.6, 0.8, 0.0), Vec3::new(0.6, -0.8, 0.0), 0.0);
+        let gold = Conductor::gold(0.0);
+        let mut sampler = IndependentSampler::new(1, 0);
+
+        // a smooth metal is a mirror tinted by its Fresnel reflectance at the angle of the ray
+        let scatter = gold.scatter(&ray, &record, &mut sampler).unwrap();
+        assert!(scatter.specular);
+        assert!((scatter.specular_ray.direction - Vec3::new(0.6, 0.8, 0.0)).length() < 1e-6);
+        assert!((scatter.attenuation - fresnel_conductor(0.8, gold.eta, gold.k)).length() < 1e-6);
+        assert_eq!(gold.albedo(&ray, &record), None);
+    }
+
+    #[test]
+    fn test_absorption_over_distance() {
+        let absorption = absorption_at_distance(Vec3::new(0.5, 1.0, 0.25), 2.0);
+        let glass = Refractive::new(1.5).with_absorption(absorption);
+        let mut sampler = IndependentSampler::new(1, 0);
+
+        // a ray leaving the glass after 4 units keeps the square of the color at 2 units
+        let outward = Vec3::new(0.0, 1.0, 0.0);
+        let leaving = HitRecord::new(4.0, 0.0, 0.0, Vec3::zero(), outward, outward, Arc::new(Empty::new()));
+        let ray = Ray::new(Vec3::new(0.0, -4.0, 0.0), outward, 0.0);
+        let attenuation = glass.scatter(&ray, &leaving, &mut sampler).unwrap().attenuation;
+        assert!((attenuation - Vec3::new(0.25, 1.0, 0.0625)).length() < 1e-5);
+
+        // light is not absorbed before it enters
+        let ray = Ray::new(Vec3::new(0.0, 4.0, 0.0), -outward, 0.0);
+        assert_eq!(glass.scatter(&ray, &leaving, &mut sampler).unwrap().attenuation, Vec3::one());
+    }
+
+    #[test]
+    fn test_principled_lobes() {
+        let constant = |value: f32| -> Arc<dyn Texture> { Arc::new(ConstantTexture::new(value, value, value)) };
+
+        let metal = Principled { metallic: constant(1.0),
+                                 roughness: constant(0.3),
+                                 ..Principled::new(ConstantTexture::new(1.0, 1.0, 1.0)) };
+        let plastic = Principled { clearcoat: constant(1.0),
+                                   sheen: constant(1.0),
+                                   ..Principled::new(ConstantTexture::new(0.5, 0.5, 0.5)) };
+
+        // the sampled lobes must agree with their densities and reflect no more than arrives
+        for (material, least) in vec![(metal, 0.95), (plastic, 0.4)] {
+            let (reflected, _) = estimate_albedo(&material, Vec3::new(-0.6, 0.8, 0.0));
+            assert!(reflected.x() < 1.05 && reflected.x() > least);
+        }
+
+        // emission leaves only the front of the surface
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let record = HitRecord::new(1.0, 0.0, 0.0, Vec3::zero(), normal, normal, Arc::new(Empty::new()));
+        let glowing = Principled { emission: constant(2.0),
+                                   ..Principled::new(ConstantTexture::new(0.5, 0.5, 0.5)) };
+        let ray = Ray::new(Vec3::new(-0.6, 0.8, 0.0), Vec3::new(0.6, -0.8, 0.0), 0.0);
+        assert_eq!(glowing.emitted(&ray, &record), Vec3::splat(2.0));
+        let behind = Ray::new(Vec3::new(-0.6, -0.8, 0.0), Vec3::new(0.6, 0.8, 0.0), 0.0);
+        assert_eq!(glowing.emitted(&behind, &record), Vec3::zero());
+    }
+
+    #[test]
+    fn test_anisotropy_follows_tangent() {
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let mut record = HitRecord::new(1.0, 0.0, 0.0, Vec3::zero(), normal, normal, Arc::new(Empty::new()));
+        let ray = Ray::new(Vec3::new(-0.6, 0.8, 0.0), Vec3::new(0.6, -0.8, 0.0), 0.0);
+        let brushed = Principled { anisotropic: Arc::new(ConstantTexture::new(1.0, 1.0, 1.0)),
+                                   ..Principled::new(ConstantTexture::new(1.0, 1.0, 1.0)) };
+
+        // the highlights are stretched along the tangent, made perpendicular to the normal
+        for &tangent in &[Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.5, 1.0)] {
+            record.tangent = Some(tangent);
+            let lobes = brushed.lobes(&ray, &record);
+            let expected = (tangent - normal * normal.dot(tangent)).normalize();
+            assert!((lobes.uvw.u() - expected).length() < 1e-6);
+            assert!((lobes.uvw.w() - normal).length() < 1e-6);
+        }
+
+        // without a usable tangent the frame is built from the normal alone
+        for &tangent in &[None, Some(normal)] {
+            record.tangent = tangent;
+            let lobes = brushed.lobes(&ray, &record);
+            assert!((lobes.uvw.w() - normal).length() < 1e-6);
+            assert!(lobes.uvw.u().dot(normal).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_coated_layers() {
+        let reflectance = |material: &Coated| estimate_albedo(material, Vec3::new(0.0, 1.0, 0.0)).0;
+
+        // a coat over a black base reflects only its Fresnel reflectance of 4% at normal incidence
+        let black = Diffuse::new(ConstantTexture::new(0.0, 0.0, 0.0), 0.0);
+        let coat = reflectance(&Coated::new(black, 1.5, 0.3));
+        assert!((coat.x() - 0.04).abs() < 0.005);
+
+        // a white base gets what the coat lets through, smooth or rough, and never more
+        for &roughness in &[0.0, 0.3] {
+            let white = Diffuse::new(ConstantTexture::new(1.0, 1.0, 1.0), 0.0);
+            let energy = reflectance(&Coated::new(white, 1.5, roughness)).x();
+            assert!(energy > 0.88 && energy < 0.94);
+        }
+
+        // coats layered over coats, either way round, still reflect what their layers let through
+        for &(outer, inner) in &[(0.3, 0.0), (0.0, 0.3), (0.3, 0.3)] {
+            let white = Diffuse::new(ConstantTexture::new(1.0, 1.0, 1.0), 0.0);
+            let nested = Coated::new(Coated::new(white, 1.5, inner), 1.5, outer);
+            let energy = reflectance(&nested).x();
+            assert!(energy > 0.82 && energy < 0.87);
+        }
+
+        // a coat that absorbs blue over a mirror turns it yellow
+        let varnish = Coated::new(Reflective::new(Vec3::one(), 0.0), 1.5, 0.2)
+            .with_absorption(Vec3::new(0.0, 0.0, 2.0), 0.5);
+        let energy = reflectance(&varnish);
+        assert!(energy.x() > 0.88 && energy.x() < 1.0 && energy.z() < 0.5);
+    }
+}
@@ -0,0 +1,50 @@
+/// Compute the root mean squared error of the pixels against the reference
+///
+/// Both images are RGB buffers of the same size.
+pub fn rmse(pixels: &[f32], reference: &[f32]) -> f32 {
+    assert_eq!(pixels.len(), reference.len());
+
+    let sum: f64 = pixels.iter()
+                         .zip(reference)
+                         .map(|(p, r)| f64::from(p - r).powi(2))
+                         .sum();
+
+    (sum / pixels.len().max(1) as f64).sqrt() as f32
+}
+
+/// Compute the relative mean squared error of the pixels against the reference
+///
+/// Every squared error is divided by the squared reference value so that
+/// errors in dark regions count as much as errors in bright regions. The
+/// small epsilon keeps black pixels of the reference from dominating.
+pub fn relative_mse(pixels: &[f32], reference: &[f32]) -> f32 {
+    assert_eq!(pixels.len(), reference.len());
+
+    let sum: f64 = pixels.iter()
+                         .zip(reference)
+                         .map(|(p, r)| f64::from(p - r).powi(2) / (f64::from(r * r) + 1e-2))
+                         .sum();
+
+    (sum / pixels.len().max(1) as f64) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_metrics() {
+        let reference = [0.0, 1.0, 2.0, 4.0];
+
+        assert_eq!(rmse(&reference, &reference), 0.0);
+        assert_eq!(relative_mse(&reference, &reference), 0.0);
+
+        let pixels = [1.0, 2.0, 3.0, 5.0];
+        assert!((rmse(&pixels, &reference) - 1.0).abs() < 1e-6);
+
+        // the same absolute error weighs less on brighter pixels
+        let bright_error = relative_mse(&[0.0, 1.0, 2.0, 5.0], &reference);
+        let dark_error = relative_mse(&[1.0, 1.0, 2.0, 4.0], &reference);
+        assert!(bright_error < dark_error);
+    }
+}
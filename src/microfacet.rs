@@ -0,0 +1,258 @@
+use std::f32::consts::PI;
+
+use glam::Vec3;
+
+/// TrowbridgeReitz is the GGX distribution of the normals of a rough surface
+///
+/// Directions are given in the local frame of the surface, where the
+/// macroscopic normal is +Z. The distribution may be stretched along X
+/// and Y by different widths for brushed surfaces. The masking and
+/// shadowing of the microfacets follows Smith's height-correlated model.
+///
+/// Bruce Walter, Stephen R. Marschner, Hongsong Li, Kenneth E. Torrance:
+/// Microfacet Models for Refraction through Rough Surfaces, EGSR 2007
+///
+/// Eric Heitz: Understanding the Masking-Shadowing Function in
+/// Microfacet-Based BRDFs, Journal of Computer Graphics Techniques Vol. 3, No. 2, 2014
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrowbridgeReitz {
+    pub alpha_x: f32,
+    pub alpha_y: f32,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha: f32) -> TrowbridgeReitz {
+        TrowbridgeReitz::anisotropic(alpha, alpha)
+    }
+
+    pub fn anisotropic(alpha_x: f32, alpha_y: f32) -> TrowbridgeReitz {
+        TrowbridgeReitz { alpha_x, alpha_y }
+    }
+
+    /// Create the distribution of a surface from its perceptual roughness between 0 and 1
+    ///
+    /// The width of the distribution is the square of the roughness,
+    /// which makes the highlights widen evenly as the roughness grows.
+    pub fn from_roughness(roughness: f32) -> TrowbridgeReitz {
+        let roughness = roughness.clamp(0.0, 1.0);
+        TrowbridgeReitz::new(roughness * roughness)
+    }
+
+    /// Whether the surface is so smooth that it is better treated as a perfect mirror
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    /// The density of microfacets with the normal h
+    pub fn d(&self, h: Vec3) -> f32 {
+        if h.z() == 0.0 {
+            return 0.0;
+        }
+
+        let x = h.x() / self.alpha_x;
+        let y = h.y() / self.alpha_y;
+        let denominator = x * x + y * y + h.z() * h.z();
+        1.0 / (PI * self.alpha_x * self.alpha_y * denominator * denominator)
+    }
+
+    /// Smith's auxiliary function of the microfacets hidden from the direction w
+    fn lambda(&self, w: Vec3) -> f32 {
+        let cos2 = w.z() * w.z();
+        if cos2 <= 0.0 {
+            return f32::MAX;
+        }
+
+        let x = self.alpha_x * w.x();
+        let y = self.alpha_y * w.y();
+        0.5 * ((1.0 + (x * x + y * y) / cos2).sqrt() - 1.0)
+    }
+
+    /// The fraction of microfacets visible from the direction w
+    pub fn g1(&self, w: Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// The fraction of microfacets visible from both wo and wi
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// The density of the normals sampled by sample_visible_normal for the direction wo
+    pub fn visible_normal_pdf(&self, wo: Vec3, h: Vec3) -> f32 {
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+
+        self.g1(wo) * wo.dot(h).max(0.0) * self.d(h) / wo.z()
+    }
+
+    /// Sample a microfacet normal in proportion to how much of it wo sees
+    ///
+    /// The hemisphere seen from wo is stretched into the configuration of
+    /// a unit roughness, where the visible normals are a projected disk.
+    ///
+    /// Eric Heitz: Sampling the GGX Distribution of Visible Normals,
+    /// Journal of Computer Graphics Techniques Vol. 7, No. 4, 2018
+    pub fn sample_visible_normal(&self, wo: Vec3, u: f32, v: f32) -> Vec3 {
+        let stretched = Vec3::new(self.alpha_x * wo.x(), self.alpha_y * wo.y(), wo.z()).normalize();
+
+        let length2 = stretched.x() * stretched.x() + stretched.y() * stretched.y();
+        let t1 = if length2 > 0.0 {
+            Vec3::new(-stretched.y(), stretched.x(), 0.0) / length2.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = stretched.cross(t1);
+
+        let r = u.sqrt();
+        let phi = 2.0 * PI * v;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + stretched.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+        let p3 = (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+
+        let normal = p1 * t1 + p2 * t2 + p3 * stretched;
+        Vec3::new(self.alpha_x * normal.x(), self.alpha_y * normal.y(), normal.z().max(0.0)).normalize()
+    }
+}
+
+/// The fraction of light a conductor reflects at an angle with the cosine cos_theta
+///
+/// The index of refraction of a conductor is the complex number eta + ik,
+/// where the extinction coefficient k describes how fast light dies out
+/// inside it. Each channel is computed with its own index.
+///
+/// See Section 8.2.1 in Physically Based Rendering by Matt Pharr, Wenzel Jakob
+/// and Greg Humphreys.
+pub fn fresnel_conductor(cos_theta: f32, eta: Vec3, k: Vec3) -> Vec3 {
+    let channel = |eta: f32, k: f32| {
+        let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+        let sin2 = 1.0 - cos2;
+        let eta2 = eta * eta;
+        let k2 = k * k;
+
+        let t0 = eta2 - k2 - sin2;
+        let a2b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+        let t1 = a2b2 + cos2;
+        let a = (0.5 * (a2b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos2.sqrt() * a;
+        let rs = (t1 - t2) / (t1 + t2);
+
+        let t3 = cos2 * a2b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+
+        0.5 * (rp + rs)
+    };
+
+    Vec3::new(channel(eta.x(), k.x()), channel(eta.y(), k.y()), channel(eta.z(), k.z()))
+}
+
+/// The fraction of light a dielectric reflects at an angle with the cosine cos_theta
+///
+/// eta is the index of refraction on the far side of the surface
+/// relative to the side the light comes from. Past the critical
+/// angle all of the light is reflected.
+pub fn fresnel_dielectric(cos_theta: f32, eta: f32) -> f32 {
+    let cos_i = cos_theta.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+/// Refract the direction w, which points away from the surface, through the normal h
+///
+/// None is returned when the light is reflected completely.
+pub fn refract(w: Vec3, h: Vec3, eta: f32) -> Option<Vec3> {
+    let cos_i = w.dot(h);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-w / eta + (cos_i / eta - cos_t) * h)
+}
+
+/// Find the microfacet normal that reflects or refracts wo into wi
+///
+/// Directions on opposite sides of the surface are refracted into a
+/// medium eta times as dense. None is returned when no microfacet facing
+/// wo can scatter it into wi.
+pub fn half_vector(wo: Vec3, wi: Vec3, eta: f32) -> Option<Vec3> {
+    let reflection = wi.z() > 0.0;
+    let h = if reflection { wo + wi } else { wo + eta * wi };
+    if h.length_squared() == 0.0 {
+        return None;
+    }
+
+    let h = if h.z() < 0.0 { -h.normalize() } else { h.normalize() };
+    if wo.dot(h) <= 0.0 || (wi.dot(h) > 0.0) != reflection {
+        return None;
+    }
+    Some(h)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_visible_normal_sampling() {
+        let distribution = TrowbridgeReitz::anisotropic(0.5, 0.2);
+        let wo = Vec3::new(0.48, 0.36, 0.8);
+
+        // the microfacets project onto the surface exactly once, and the
+        // visible normals are a distribution whose mean the samples must match
+        let mut projected = 0.0;
+        let mut visible = 0.0;
+        let mut visible_z = 0.0;
+        let steps = 400;
+        for i in 0..steps {
+            for j in 0..steps {
+                let cos_theta = (i as f32 + 0.5) / steps as f32;
+                let phi = 2.0 * PI * (j as f32 + 0.5) / steps as f32;
+                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                let h = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+                let pdf = distribution.visible_normal_pdf(wo, h);
+                projected += distribution.d(h) * cos_theta;
+                visible += pdf;
+                visible_z += pdf * h.z();
+            }
+        }
+        let area = 2.0 * PI / (steps * steps) as f32;
+        assert!((projected * area - 1.0).abs() < 1e-2);
+        assert!((visible * area - 1.0).abs() < 1e-2);
+
+        let mut sampled_z = 0.0;
+        for i in 0..64 {
+            for j in 0..64 {
+                let (u, v) = ((i as f32 + 0.5) / 64.0, (j as f32 + 0.5) / 64.0);
+                let h = distribution.sample_visible_normal(wo, u, v);
+                assert!(h.z() >= 0.0 && wo.dot(h) >= 0.0);
+                sampled_z += h.z() / 4096.0;
+            }
+        }
+        assert!((sampled_z - visible_z * area).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_fresnel_conductor() {
+        // at normal incidence gold reflects more red than blue, and everything reflects at grazing angles
+        let gold = fresnel_conductor(1.0, Vec3::new(0.143, 0.375, 1.442), Vec3::new(3.983, 2.386, 1.603));
+        assert!(gold.x() > gold.z() && gold.x() < 1.0);
+        assert!((fresnel_conductor(0.0, gold, gold) - Vec3::one()).length() < 1e-5);
+
+        // without extinction a conductor reflects as much as a dielectric of the same index
+        for &cos_theta in &[1.0, 0.7, 0.2] {
+            let conductor = fresnel_conductor(cos_theta, Vec3::splat(1.5), Vec3::zero());
+            assert!((conductor.x() - fresnel_dielectric(cos_theta, 1.5)).abs() < 1e-5);
+        }
+    }
+}
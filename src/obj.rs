@@ -0,0 +1,230 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use glam::{Vec2, Vec3};
+use tobj;
+
+use materials::{Diffuse, Light, Material, Principled, Reflective, Refractive};
+use texture::{ConstantTexture, ImageTexture, Texture};
+use triangle::{MeshData, MeshError, Normals, TriangleMesh};
+
+/// Load a Wavefront OBJ file together with the materials of its MTL files
+///
+/// Faces use the material their MTL file assigns to them and the default
+/// material otherwise. Models without normals are given generated normals.
+/// Emissive materials are not added to the lights of a scene, so they are
+/// only found by paths that hit them.
+pub fn load(filepath: &str,
+            default_material: Arc<dyn Material>,
+            normals: Normals)
+            -> Result<TriangleMesh, MeshError> {
+    let path = Path::new(filepath);
+    let (models, obj_materials) =
+        tobj::load_obj(path).map_err(|error| MeshError::Load(format!("{}: {}", filepath, error)))?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut materials = vec![default_material];
+    for material in &obj_materials {
+        materials.push(convert_material(material, directory)?);
+    }
+
+    let mut data = MeshData::default();
+
+    for model in &models {
+        let mut model_data = model_data(&model.mesh, normals)?;
+
+        // material 0 is the default material, the MTL materials follow it
+        let material_id = match model.mesh.material_id {
+            Some(id) => id as u32 + 1,
+            None => 0,
+        };
+        model_data.material_ids = vec![material_id; model_data.triangle_count()];
+
+        data.append(model_data);
+    }
+
+    TriangleMesh::with_materials(data, materials)
+}
+
+/// Convert the buffers of a model and generate its normals if it has none
+fn model_data(mesh: &tobj::Mesh, normals: Normals) -> Result<MeshData, MeshError> {
+    let data = MeshData { positions: mesh.positions.chunks(3).map(|p| Vec3::new(p[0], p[1], p[2])).collect(),
+                          normals: mesh.normals.chunks(3).map(|n| Vec3::new(n[0], n[1], n[2])).collect(),
+                          uvs: mesh.texcoords.chunks(2).map(|t| Vec2::new(t[0], t[1])).collect(),
+                          indices: mesh.indices.clone(),
+                          ..MeshData::default() };
+    data.validate()?;
+
+    Ok(data.with_normals(normals))
+}
+
+/// The keys of the physically based extension of MTL files
+const PBR_KEYS: [&str; 5] = ["Pr", "Pm", "Ps", "Pc", "aniso"];
+
+/// Map a material of an MTL file onto the closest material of the renderer
+///
+/// Materials with any of the physically based parameters roughness (Pr),
+/// metallic (Pm), sheen (Ps), clearcoat (Pc) or anisotropy (aniso) become
+/// principled. Otherwise emissive materials (Ke) become lights, transparent
+/// materials (d < 1 or illum 4, 6 or 7) become refractive with their index of refraction (Ni),
+/// mirrors (illum 3) and materials whose specular color (Ks) outshines their
+/// diffuse color become reflective, with the fuzz derived from the specular
+/// exponent (Ns), and everything else becomes diffuse with its diffuse
+/// color (Kd) or texture (map_Kd).
+fn convert_material(material: &tobj::Material,
+                    directory: &Path)
+                    -> Result<Arc<dyn Material>, MeshError> {
+    let emission = match material.unknown_param.get("Ke") {
+        Some(value) => parse_color(value).ok_or_else(|| {
+                           MeshError::Load(format!("material '{}' has an invalid Ke '{}'",
+                                                   material.name, value))
+                       })?,
+        None => Vec3::zero(),
+    };
+    let diffuse = Vec3::from(material.diffuse);
+    let specular = Vec3::from(material.specular);
+    let illumination = material.illumination_model.unwrap_or(2);
+
+    if PBR_KEYS.iter().any(|key| material.unknown_param.contains_key(*key)) {
+        return principled_material(material, directory, emission);
+    }
+
+    if emission.max_element() > 0.0 {
+        return Ok(Arc::new(Light::new(ConstantTexture::new(emission.x(),
+                                                           emission.y(),
+                                                           emission.z()))));
+    }
+
+    if material.dissolve < 1.0 || [4, 6, 7].contains(&illumination) {
+        // an index of 1 is the default of MTL files that leave it out, so assume glass
+        let index = if material.optical_density > 1.0 { material.optical_density } else { 1.5 };
+        return Ok(Arc::new(Refractive::new(index)));
+    }
+
+    if illumination == 3 || specular.max_element() > diffuse.max_element() {
+        let fuzz = (2.0 / (material.shininess.max(0.0) + 2.0)).sqrt();
+        return Ok(Arc::new(Reflective::new(specular, fuzz)));
+    }
+
+    Ok(Arc::new(Diffuse::from_box(diffuse_texture(material, directory)?, 0.0)))
+}
+
+/// Convert a material of the physically based extension into a principled material
+///
+/// The diffuse color or texture is the base color, transparent materials
+/// transmit the light they do not cover and the index of refraction (Ni)
+/// sets the specular reflectance.
+fn principled_material(material: &tobj::Material,
+                       directory: &Path,
+                       emission: Vec3)
+                       -> Result<Arc<dyn Material>, MeshError> {
+    let constant = |value: f32| -> Arc<dyn Texture> { Arc::new(ConstantTexture::new(value, value, value)) };
+    let parameter = |key: &str| -> Result<Option<Arc<dyn Texture>>, MeshError> {
+        match material.unknown_param.get(key) {
+            Some(value) => match value.trim().parse::<f32>() {
+                Ok(value) => Ok(Some(constant(value))),
+                Err(_) => Err(MeshError::Load(format!("material '{}' has an invalid {} '{}'",
+                                                      material.name, key, value))),
+            },
+            None => Ok(None),
+        }
+    };
+
+    let mut principled = Principled::from_box(diffuse_texture(material, directory)?);
+    principled.roughness = parameter("Pr")?.unwrap_or(principled.roughness);
+    principled.metallic = parameter("Pm")?.unwrap_or(principled.metallic);
+    principled.sheen = parameter("Ps")?.unwrap_or(principled.sheen);
+    principled.clearcoat = parameter("Pc")?.unwrap_or(principled.clearcoat);
+    principled.anisotropic = parameter("aniso")?.unwrap_or(principled.anisotropic);
+    principled.transmission = constant(1.0 - material.dissolve.clamp(0.0, 1.0));
+    principled.emission = Arc::new(ConstantTexture::new(emission.x(), emission.y(), emission.z()));
+
+    // a specular of 0.5 is the reflectance of an index of 1.5
+    if material.optical_density > 1.0 {
+        let reflectance = ((material.optical_density - 1.0) / (material.optical_density + 1.0)).powi(2);
+        principled.specular = constant(reflectance / 0.08);
+    }
+
+    Ok(Arc::new(principled))
+}
+
+/// Find the diffuse texture (map_Kd) of a material, or its diffuse color (Kd)
+fn diffuse_texture(material: &tobj::Material, directory: &Path) -> Result<Arc<dyn Texture>, MeshError> {
+    if material.diffuse_texture.is_empty() {
+        let diffuse = material.diffuse;
+        return Ok(Arc::new(ConstantTexture::new(diffuse[0], diffuse[1], diffuse[2])));
+    }
+
+    let path = directory.join(&material.diffuse_texture);
+    if !path.is_file() {
+        return Err(MeshError::Load(format!("texture '{}' of material '{}' does not exist",
+                                           path.display(),
+                                           material.name)));
+    }
+    Ok(Arc::new(ImageTexture::new(&path.to_string_lossy())))
+}
+
+/// Parse an RGB color, or a single value used for all three channels
+fn parse_color(value: &str) -> Option<Vec3> {
+    let channels = value.split_whitespace()
+                        .map(|channel| channel.parse::<f32>().ok())
+                        .collect::<Option<Vec<f32>>>()?;
+
+    match channels.as_slice() {
+        [value] => Some(Vec3::splat(*value)),
+        [r, g, b] => Some(Vec3::new(*r, *g, *b)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::f32;
+    use std::fs;
+
+    use hitable::Hitable;
+    use materials::Empty;
+    use ray::Ray;
+
+    #[test]
+    fn test_load_obj_with_materials() {
+        let directory = env::temp_dir().join(format!("renderama-obj-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        fs::write(directory.join("quad.mtl"),
+                  "newmtl red\nKd 1.0 0.0 0.0\n\nnewmtl lamp\nKd 0.0 0.0 0.0\nKe 4.0 4.0 4.0\n").unwrap();
+        // two quads without normals, one in each material
+        fs::write(directory.join("quad.obj"),
+                  "mtllib quad.mtl\n\
+                   v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+                   v 0 0 -1\nv 1 0 -1\nv 1 1 -1\nv 0 1 -1\n\
+                   vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+                   usemtl red\nf 1/1 2/2 3/3 4/4\n\
+                   usemtl lamp\nf 5/1 6/2 7/3 8/4\n").unwrap();
+        let path = directory.join("quad.obj");
+        let path = path.to_str().unwrap();
+
+        for &normals in &[Normals::Smooth, Normals::Flat] {
+            let mesh = load(path, Arc::new(Empty::new()), normals).unwrap();
+
+            let ray = Ray::new(Vec3::new(0.25, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+            let hit = mesh.hit(&ray, 1e-4, f32::MAX).unwrap();
+            assert!((hit.parameter - 1.0).abs() < 1e-5);
+            assert_eq!(hit.shading_normal, Vec3::new(0.0, 0.0, 1.0));
+            assert_eq!(hit.material.emitted(&ray, &hit), Vec3::zero());
+
+            // past the first quad the ray finds the glowing second one
+            let ray = Ray::new(Vec3::new(0.5, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+            let hit = mesh.hit(&ray, 1.5, f32::MAX).unwrap();
+            assert!((hit.parameter - 2.0).abs() < 1e-5);
+            assert_eq!(hit.material.emitted(&ray, &hit), Vec3::new(4.0, 4.0, 4.0));
+        }
+
+        let missing = directory.join("missing.obj");
+        assert!(load(missing.to_str().unwrap(), Arc::new(Empty::new()), Normals::Smooth).is_err());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,82 @@
+use std::path::Path;
+
+use image;
+use image2;
+use image2::{Image, ImageBuf, Rgb};
+
+use utils;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    HDR,
+    PNG,
+}
+
+impl OutputFormat {
+    /// Find the output format with the given name, e.g. "hdr" or "png"
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        match name.to_lowercase().as_str() {
+            "hdr" => Some(OutputFormat::HDR),
+            "png" => Some(OutputFormat::PNG),
+            _ => None,
+        }
+    }
+
+    /// Determine the output format from the extension of the given path
+    pub fn from_path(path: &str) -> Option<OutputFormat> {
+        Path::new(path).extension()
+                       .and_then(|extension| extension.to_str())
+                       .and_then(OutputFormat::from_name)
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::HDR => "hdr",
+            OutputFormat::PNG => "png",
+        }
+    }
+}
+
+/// Write the RGB pixel buffer to the given path
+///
+/// HDR output stores the radiance values as they were rendered. PNG output
+/// is clamped to [0, 1] and gamma corrected before being quantized to 8 bits.
+pub fn write_image(path: &str,
+                   format: OutputFormat,
+                   pixels: &[f32],
+                   width: usize,
+                   height: usize)
+                   -> Result<(), String> {
+    match format {
+        OutputFormat::HDR => {
+            let buffer: ImageBuf<f32, Rgb> = ImageBuf::new_from(width, height, pixels.to_vec());
+            image2::io::write(path, &buffer).map_err(|e| format!("{:?}", e))
+        }
+        OutputFormat::PNG => {
+            let bytes: Vec<u8> =
+                pixels.iter()
+                      .map(|&p| (255.0 * utils::gamma_correct(utils::clamp(p, 0.0, 1.0), 2.2)) as u8)
+                      .collect();
+            image::save_buffer(path,
+                               &bytes,
+                               width as u32,
+                               height as u32,
+                               image::ColorType::RGB(8)).map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// Read the RGB pixel buffer, width and height of the HDR image at the given path
+pub fn read_hdr(path: &str) -> Result<(Vec<f32>, usize, usize), String> {
+    // stb_image does not report a missing file, so check for it first
+    if !Path::new(path).is_file() {
+        return Err(format!("{} does not exist", path));
+    }
+
+    let image = image2::io::read_f32::<_, Rgb>(path).map_err(|e| format!("{:?}", e))?;
+    if image.width() == 0 || image.height() == 0 {
+        return Err(format!("{} is not a readable image", path));
+    }
+
+    Ok((image.data().to_vec(), image.width(), image.height()))
+}
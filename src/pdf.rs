@@ -1,13 +1,12 @@
 use std::f32::consts::PI;
-use std::sync::Arc;
 
 use glam::Vec3;
-use rand::rngs::ThreadRng;
-use rand::Rng;
 
 use basis::OrthonormalBasis;
 use hitable::Hitable;
-use sampling::uniform_sample_hemisphere;
+use microfacet::{fresnel_dielectric, half_vector, refract, TrowbridgeReitz};
+use sampler::Sampler;
+use sampling::cosine_sample_hemisphere;
 
 pub enum PDF<'a> {
     CosinePDF {
@@ -15,11 +14,30 @@ pub enum PDF<'a> {
     },
     HitablePDF {
         origin: Vec3,
-        hitable: Arc<dyn Hitable>,
+        time: f32,
+        hitable: &'a dyn Hitable,
     },
+    /// Reflections off the microfacets that are visible from wo, given in the basis uvw
+    GGXPDF {
+        uvw: OrthonormalBasis,
+        wo: Vec3,
+        distribution: TrowbridgeReitz,
+    },
+    /// Reflections and refractions through the microfacets visible from wo, given in the basis uvw
+    ///
+    /// Each sampled microfacet reflects or refracts with the probability
+    /// of its Fresnel reflectance for the relative index of refraction eta.
+    DielectricPDF {
+        uvw: OrthonormalBasis,
+        wo: Vec3,
+        eta: f32,
+        distribution: TrowbridgeReitz,
+    },
+    /// A mixture of distributions, each sampled in proportion to its weight
+    ///
+    /// The weights are expected to sum to one.
     MixturePDF {
-        cosine_pdf: &'a PDF<'a>,
-        hitable_pdf: &'a PDF<'a>,
+        pdfs: Vec<(f32, PDF<'a>)>,
     },
 }
 
@@ -35,25 +53,97 @@ impl<'a> PDF<'a> {
                     0.0
                 }
             }
-            PDF::HitablePDF { origin, hitable } => hitable.pdf_value(*origin, direction),
-            PDF::MixturePDF { cosine_pdf,
-                              hitable_pdf, } => {
-                0.5 * cosine_pdf.value(direction) + 0.5 * hitable_pdf.value(direction)
+            PDF::HitablePDF { origin,
+                              time,
+                              hitable, } => hitable.pdf_value(*origin, direction, *time),
+            PDF::GGXPDF { uvw,
+                          wo,
+                          distribution, } => {
+                let wi = uvw.to_local(&direction.normalize());
+                let h = (*wo + wi).normalize();
+
+                // the density of the normal is divided by the Jacobian of the reflection
+                if wi.z() > 0.0 && wo.dot(h) > 0.0 {
+                    distribution.visible_normal_pdf(*wo, h) / (4.0 * wo.dot(h))
+                } else {
+                    0.0
+                }
+            }
+            PDF::DielectricPDF { uvw,
+                                 wo,
+                                 eta,
+                                 distribution, } => {
+                let wi = uvw.to_local(&direction.normalize());
+                let h = match half_vector(*wo, wi, *eta) {
+                    Some(h) => h,
+                    None => return 0.0,
+                };
+
+                let visible = distribution.visible_normal_pdf(*wo, h);
+                let fresnel = fresnel_dielectric(wo.dot(h), *eta);
+
+                if wi.z() > 0.0 {
+                    fresnel * visible / (4.0 * wo.dot(h))
+                } else {
+                    let denominator = wo.dot(h) + eta * wi.dot(h);
+                    (1.0 - fresnel) * visible * eta * eta * wi.dot(h).abs() / (denominator * denominator)
+                }
             }
+            PDF::MixturePDF { pdfs } => pdfs.iter().map(|(weight, pdf)| weight * pdf.value(direction)).sum(),
         }
     }
 
-    pub fn generate(&self, rng: &mut ThreadRng) -> Vec3 {
+    /// Sample a direction from the distribution
+    ///
+    /// None is returned when the distribution has no direction to
+    /// offer, in which case the sample contributes nothing.
+    pub fn generate(&self, sampler: &mut dyn Sampler) -> Option<Vec3> {
         match self {
-            PDF::CosinePDF { uvw } => uvw.local(&uniform_sample_hemisphere(rng)),
-            PDF::HitablePDF { origin, hitable } => hitable.pdf_random(*origin, rng),
-            PDF::MixturePDF { cosine_pdf,
-                              hitable_pdf, } => {
-                if rng.gen::<f32>() < 0.5 {
-                    cosine_pdf.generate(rng)
+            PDF::CosinePDF { uvw } => {
+                let (u, v) = sampler.get_2d();
+                Some(uvw.local(&cosine_sample_hemisphere(u, v)))
+            }
+            PDF::HitablePDF { origin,
+                              time,
+                              hitable, } => hitable.pdf_random(*origin, *time, sampler),
+            PDF::GGXPDF { uvw,
+                          wo,
+                          distribution, } => {
+                let (u, v) = sampler.get_2d();
+                let h = distribution.sample_visible_normal(*wo, u, v);
+                let wi = 2.0 * wo.dot(h) * h - *wo;
+                Some(uvw.local(&wi))
+            }
+            PDF::DielectricPDF { uvw,
+                                 wo,
+                                 eta,
+                                 distribution, } => {
+                let (u, v) = sampler.get_2d();
+                let h = distribution.sample_visible_normal(*wo, u, v);
+
+                let refracted = if sampler.get_1d() < fresnel_dielectric(wo.dot(h), *eta) {
+                    None
                 } else {
-                    hitable_pdf.generate(rng)
+                    refract(*wo, h, *eta)
+                };
+
+                // a rough microfacet can send light to the other side than it meant to,
+                // where the density belongs to the other lobe, so the sample is dropped
+                let wi = refracted.unwrap_or_else(|| 2.0 * wo.dot(h) * h - *wo);
+                if wi.z() == 0.0 || (wi.z() < 0.0) != refracted.is_some() {
+                    return None;
+                }
+                Some(uvw.local(&wi))
+            }
+            PDF::MixturePDF { pdfs } => {
+                let mut choice = sampler.get_1d();
+                for (weight, pdf) in pdfs {
+                    if choice < *weight {
+                        return pdf.generate(sampler);
+                    }
+                    choice -= weight;
                 }
+                pdfs.last().and_then(|(_, pdf)| pdf.generate(sampler))
             }
         }
     }
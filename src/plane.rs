@@ -2,13 +2,12 @@ use std::f32;
 use std::sync::Arc;
 
 use glam::Vec3;
-use rand::rngs::ThreadRng;
-use rand::Rng;
 
 use aabb::AABB;
 use hitable::{HitRecord, Hitable};
 use materials::Material;
 use ray::Ray;
+use sampler::Sampler;
 
 #[derive(Clone)]
 pub enum Axis {
@@ -63,6 +62,11 @@ impl Plane {
                 k,
                 material }
     }
+
+    /// Calculate the area of the plane
+    pub fn area(&self) -> f32 {
+        (self.r1 - self.r0) * (self.s1 - self.s0)
+    }
 }
 
 impl Hitable for Plane {
@@ -169,21 +173,53 @@ impl Hitable for Plane {
         }
     }
 
-    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
-        if let Some(hit) = self.hit(&Ray::new(origin, direction, 0.0), 0.001, f32::MAX) {
-            let area = (self.r1 - self.r0) * (self.s1 - self.s0);
-            let distance_squared = hit.parameter * hit.parameter * direction.length_squared();
-            let cosine = direction.dot(hit.shading_normal).abs() / direction.length();
+    /// Convert the density of uniformly sampling the area of the plane to solid angle
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        if let Some(hit) = self.hit(&Ray::new(origin, direction, time), 0.001, f32::MAX) {
+            let area = self.area();
+            // rays have a unit direction so the parameter is the distance to the hit
+            let distance_squared = hit.parameter * hit.parameter;
+            let cosine = direction.normalize().dot(hit.shading_normal).abs();
             distance_squared / (cosine * area)
         } else {
             0.0
         }
     }
 
-    fn pdf_random(&self, origin: Vec3, rng: &mut ThreadRng) -> Vec3 {
-        let random_point = Vec3::new(self.r0 + rng.gen::<f32>() * (self.r1 - self.r0),
-                                     self.k,
-                                     self.s0 + rng.gen::<f32>() * (self.s1 - self.s0));
-        random_point - origin
+    /// Sample a direction from origin towards a uniformly chosen point on the plane
+    fn pdf_random(&self, origin: Vec3, _time: f32, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        let (u, v) = sampler.get_2d();
+        let r = self.r0 + u * (self.r1 - self.r0);
+        let s = self.s0 + v * (self.s1 - self.s0);
+
+        let random_point = match self.axis {
+            Axis::XY => Vec3::new(r, s, self.k),
+            Axis::YZ => Vec3::new(self.k, r, s),
+            Axis::XZ => Vec3::new(r, self.k, s),
+        };
+        Some(random_point - origin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use materials::Empty;
+    use sampler::IndependentSampler;
+
+    #[test]
+    fn test_plane_sampling() {
+        let planes = vec![Plane::new(Axis::XY, 0.0, 1.0, 2.0, 4.0, -3.0, Empty::new()),
+                          Plane::new(Axis::YZ, 0.0, 1.0, 2.0, 4.0, -3.0, Empty::new()),
+                          Plane::new(Axis::XZ, 0.0, 1.0, 2.0, 4.0, -3.0, Empty::new())];
+        let origin = Vec3::new(5.0, 5.0, 5.0);
+        let mut sampler = IndependentSampler::new(100, 0);
+
+        for plane in &planes {
+            for _ in 0..100 {
+                let direction = plane.pdf_random(origin, 0.0, &mut sampler).unwrap();
+                assert!(plane.pdf_value(origin, direction, 0.0) > 0.0);
+            }
+        }
     }
 }
@@ -0,0 +1,443 @@
+use std::fs;
+use std::str;
+use std::sync::Arc;
+
+use glam::{Vec2, Vec3};
+
+use materials::Material;
+use triangle::{MeshData, MeshError, Normals, TriangleMesh};
+
+/// The encoding of the elements that follow the header
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+/// The scalar types a property can be stored as
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Scalar {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl Scalar {
+    fn from_name(name: &str) -> Option<Scalar> {
+        match name {
+            "char" | "int8" => Some(Scalar::Int8),
+            "uchar" | "uint8" => Some(Scalar::UInt8),
+            "short" | "int16" => Some(Scalar::Int16),
+            "ushort" | "uint16" => Some(Scalar::UInt16),
+            "int" | "int32" => Some(Scalar::Int32),
+            "uint" | "uint32" => Some(Scalar::UInt32),
+            "float" | "float32" => Some(Scalar::Float32),
+            "double" | "float64" => Some(Scalar::Float64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::Int8 | Scalar::UInt8 => 1,
+            Scalar::Int16 | Scalar::UInt16 => 2,
+            Scalar::Int32 | Scalar::UInt32 | Scalar::Float32 => 4,
+            Scalar::Float64 => 8,
+        }
+    }
+
+    /// The value that stands for full intensity in a color of this type
+    fn color_scale(self) -> f64 {
+        match self {
+            Scalar::Int8 => 127.0,
+            Scalar::UInt8 => 255.0,
+            Scalar::Int16 => 32767.0,
+            Scalar::UInt16 => 65535.0,
+            Scalar::Int32 => 2_147_483_647.0,
+            Scalar::UInt32 => 4_294_967_295.0,
+            Scalar::Float32 | Scalar::Float64 => 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Property {
+    Scalar { name: String, scalar: Scalar },
+    List { name: String, count: Scalar, item: Scalar },
+}
+
+#[derive(Clone, Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Reads the values of the elements one at a time in the format of the file
+struct Reader<'a> {
+    format: Format,
+    body: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read(&mut self, scalar: Scalar) -> Result<f64, MeshError> {
+        match self.format {
+            Format::Ascii => self.read_ascii(),
+            Format::BinaryLittleEndian => self.read_binary(scalar, false),
+            Format::BinaryBigEndian => self.read_binary(scalar, true),
+        }
+    }
+
+    fn read_ascii(&mut self) -> Result<f64, MeshError> {
+        while self.position < self.body.len() && self.body[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+        let start = self.position;
+        while self.position < self.body.len() && !self.body[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+
+        if start == self.position {
+            return Err(MeshError::Load(String::from("the file ends before all elements are read")));
+        }
+
+        let token = str::from_utf8(&self.body[start..self.position]).unwrap_or("");
+        token.parse::<f64>()
+             .map_err(|_| MeshError::Load(format!("'{}' is not a number", token)))
+    }
+
+    fn read_binary(&mut self, scalar: Scalar, big_endian: bool) -> Result<f64, MeshError> {
+        let size = scalar.size();
+        if self.position + size > self.body.len() {
+            return Err(MeshError::Load(String::from("the file ends before all elements are read")));
+        }
+
+        let mut bytes = [0u8; 8];
+        bytes[..size].copy_from_slice(&self.body[self.position..self.position + size]);
+        if big_endian {
+            bytes[..size].reverse();
+        }
+        self.position += size;
+
+        Ok(match scalar {
+            Scalar::Int8 => f64::from(bytes[0] as i8),
+            Scalar::UInt8 => f64::from(bytes[0]),
+            Scalar::Int16 => f64::from(i16::from_le_bytes([bytes[0], bytes[1]])),
+            Scalar::UInt16 => f64::from(u16::from_le_bytes([bytes[0], bytes[1]])),
+            Scalar::Int32 => f64::from(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            Scalar::UInt32 => f64::from(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            Scalar::Float32 => f64::from(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            Scalar::Float64 => f64::from_le_bytes(bytes),
+        })
+    }
+}
+
+/// Load a PLY file into a mesh with a single material
+///
+/// Files without normals are given generated normals. The vertex colors of
+/// a file tint the material, so a white material shows them as they are.
+pub fn load(filepath: &str,
+            material: Arc<dyn Material>,
+            normals: Normals)
+            -> Result<TriangleMesh, MeshError> {
+    let data = read(filepath)?;
+    TriangleMesh::new(data.with_normals(normals), material)
+}
+
+/// Read the vertices and faces of an ASCII or binary PLY file
+///
+/// Vertices keep their position and, when the file has them, their normal,
+/// texture coordinates and color. Polygons are split into triangle fans and
+/// all other elements are skipped.
+pub fn read(filepath: &str) -> Result<MeshData, MeshError> {
+    let contents = fs::read(filepath).map_err(|error| MeshError::Load(format!("{}: {}", filepath, error)))?;
+    parse(&contents).map_err(|error| match error {
+                        MeshError::Load(message) => MeshError::Load(format!("{}: {}", filepath, message)),
+                        error => error,
+                    })
+}
+
+fn parse(contents: &[u8]) -> Result<MeshData, MeshError> {
+    let (format, elements, body_start) = parse_header(contents)?;
+    let mut reader = Reader { format,
+                              body: &contents[body_start..],
+                              position: 0 };
+
+    let mut data = MeshData::default();
+
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => read_vertices(&mut reader, element, &mut data)?,
+            "face" => read_faces(&mut reader, element, &mut data)?,
+            _ => {
+                for _ in 0..element.count {
+                    read_element(&mut reader, element)?;
+                }
+            }
+        }
+    }
+
+    data.validate()?;
+    Ok(data)
+}
+
+fn parse_header(contents: &[u8]) -> Result<(Format, Vec<Element>, usize), MeshError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut position = 0;
+    let mut first = true;
+
+    loop {
+        let end = match contents[position..].iter().position(|&byte| byte == b'\n') {
+            Some(offset) => position + offset,
+            None => return Err(MeshError::Load(String::from("the header has no end_header"))),
+        };
+        let line = str::from_utf8(&contents[position..end])
+            .map_err(|_| MeshError::Load(String::from("the header is not text")))?;
+        position = end + 1;
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        if first {
+            if words != ["ply"] {
+                return Err(MeshError::Load(String::from("the file is not a PLY file")));
+            }
+            first = false;
+            continue;
+        }
+
+        match words.as_slice() {
+            ["format", name, _version] => {
+                format = Some(match *name {
+                                  "ascii" => Format::Ascii,
+                                  "binary_little_endian" => Format::BinaryLittleEndian,
+                                  "binary_big_endian" => Format::BinaryBigEndian,
+                                  _ => return Err(MeshError::Load(format!("unknown format '{}'", name))),
+                              });
+            }
+            ["element", name, count] => {
+                let count = count.parse()
+                                 .map_err(|_| MeshError::Load(format!("invalid count of element '{}'", name)))?;
+                elements.push(Element { name: name.to_string(),
+                                        count,
+                                        properties: Vec::new() });
+            }
+            ["property", "list", count, item, name] => {
+                let property = Property::List { name: name.to_string(),
+                                                count: scalar(count)?,
+                                                item: scalar(item)? };
+                match elements.last_mut() {
+                    Some(element) => element.properties.push(property),
+                    None => return Err(MeshError::Load(format!("property '{}' has no element", name))),
+                }
+            }
+            ["property", scalar_type, name] => {
+                let property = Property::Scalar { name: name.to_string(),
+                                                  scalar: scalar(scalar_type)? };
+                match elements.last_mut() {
+                    Some(element) => element.properties.push(property),
+                    None => return Err(MeshError::Load(format!("property '{}' has no element", name))),
+                }
+            }
+            ["end_header"] => break,
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(MeshError::Load(format!("unknown header line '{}'", line.trim()))),
+        }
+    }
+
+    match format {
+        Some(format) => Ok((format, elements, position)),
+        None => Err(MeshError::Load(String::from("the header has no format"))),
+    }
+}
+
+fn scalar(name: &str) -> Result<Scalar, MeshError> {
+    Scalar::from_name(name).ok_or_else(|| MeshError::Load(format!("unknown property type '{}'", name)))
+}
+
+/// Read every property of an element, with lists as all of their items
+fn read_element(reader: &mut Reader, element: &Element) -> Result<Vec<Vec<f64>>, MeshError> {
+    let mut values = Vec::with_capacity(element.properties.len());
+
+    for property in &element.properties {
+        match property {
+            Property::Scalar { scalar, .. } => values.push(vec![reader.read(*scalar)?]),
+            Property::List { count, item, .. } => {
+                // a corrupt count must not reserve memory, so items are read until the file runs out
+                let count = whole_number(reader.read(*count)?, "list count")?;
+                let mut items = Vec::new();
+                for _ in 0..count {
+                    items.push(reader.read(*item)?);
+                }
+                values.push(items);
+            }
+        }
+    }
+
+    Ok(values)
+}
+
+/// Check that a value read as a count or an index is a whole number that fits in a u32
+fn whole_number(value: f64, name: &str) -> Result<u32, MeshError> {
+    if value < 0.0 || value.fract() != 0.0 || value > f64::from(u32::MAX) {
+        return Err(MeshError::Load(format!("{} {} is not a whole number", name, value)));
+    }
+    Ok(value as u32)
+}
+
+/// Find the index of the first scalar property with one of the given names
+fn find(element: &Element, names: &[&str]) -> Option<(usize, Scalar)> {
+    element.properties
+           .iter()
+           .enumerate()
+           .filter_map(|(index, property)| match property {
+               Property::Scalar { name, scalar } if names.contains(&name.as_str()) => Some((index, *scalar)),
+               _ => None,
+           })
+           .next()
+}
+
+fn read_vertices(reader: &mut Reader, element: &Element, data: &mut MeshData) -> Result<(), MeshError> {
+    let position = [find(element, &["x"]), find(element, &["y"]), find(element, &["z"])];
+    let normal = [find(element, &["nx"]), find(element, &["ny"]), find(element, &["nz"])];
+    let uv = [find(element, &["u", "s", "texture_u", "texture_s"]),
+              find(element, &["v", "t", "texture_v", "texture_t"])];
+    let color = [find(element, &["red", "r"]),
+                 find(element, &["green", "g"]),
+                 find(element, &["blue", "b"])];
+
+    let all = |properties: &[Option<(usize, Scalar)>]| -> Option<Vec<(usize, Scalar)>> {
+        properties.iter().cloned().collect()
+    };
+
+    let position = all(&position).ok_or_else(|| {
+                                      MeshError::Load(String::from("vertices have no x, y and z"))
+                                  })?;
+    let normal = all(&normal);
+    let uv = all(&uv);
+    let color = all(&color);
+
+    let get = |values: &[Vec<f64>], (index, _): (usize, Scalar)| values[index][0];
+
+    for _ in 0..element.count {
+        let values = read_element(reader, element)?;
+
+        data.positions.push(Vec3::new(get(&values, position[0]) as f32,
+                                      get(&values, position[1]) as f32,
+                                      get(&values, position[2]) as f32));
+        if let Some(normal) = &normal {
+            data.normals.push(Vec3::new(get(&values, normal[0]) as f32,
+                                        get(&values, normal[1]) as f32,
+                                        get(&values, normal[2]) as f32));
+        }
+        if let Some(uv) = &uv {
+            data.uvs.push(Vec2::new(get(&values, uv[0]) as f32, get(&values, uv[1]) as f32));
+        }
+        if let Some(color) = &color {
+            let channel = |property: (usize, Scalar)| {
+                (get(&values, property) / property.1.color_scale()) as f32
+            };
+            data.colors.push(Vec3::new(channel(color[0]), channel(color[1]), channel(color[2])));
+        }
+    }
+
+    Ok(())
+}
+
+fn read_faces(reader: &mut Reader, element: &Element, data: &mut MeshData) -> Result<(), MeshError> {
+    let indices = element.properties
+                         .iter()
+                         .position(|property| match property {
+                             Property::List { name, .. } => name == "vertex_indices" || name == "vertex_index",
+                             _ => false,
+                         })
+                         .ok_or_else(|| MeshError::Load(String::from("faces have no vertex_indices")))?;
+
+    for _ in 0..element.count {
+        let values = read_element(reader, element)?;
+        let polygon = values[indices].iter()
+                                     .map(|&index| whole_number(index, "vertex index"))
+                                     .collect::<Result<Vec<u32>, MeshError>>()?;
+
+        for i in 1..polygon.len().saturating_sub(1) {
+            data.indices.push(polygon[0]);
+            data.indices.push(polygon[i]);
+            data.indices.push(polygon[i + 1]);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ply_formats() {
+        let header = |format: &str| {
+            format!("ply\nformat {} 1.0\ncomment a unit square\n\
+                     element vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+                     property float s\nproperty float t\n\
+                     property uchar red\nproperty uchar green\nproperty uchar blue\n\
+                     element face 1\nproperty list uchar int vertex_indices\nend_header\n",
+                    format)
+        };
+        let vertices = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0f32]];
+
+        let mut ascii = header("ascii").into_bytes();
+        for v in &vertices {
+            ascii.extend(format!("{} {} {} {} {} 255 0 51\n", v[0], v[1], v[2], v[0], v[1]).bytes());
+        }
+        ascii.extend(b"4 0 1 2 3\n");
+
+        let mut little = header("binary_little_endian").into_bytes();
+        let mut big = header("binary_big_endian").into_bytes();
+        for v in &vertices {
+            for value in &[v[0], v[1], v[2], v[0], v[1]] {
+                little.extend(&value.to_le_bytes());
+                big.extend(&value.to_be_bytes());
+            }
+            little.extend(&[255, 0, 51]);
+            big.extend(&[255, 0, 51]);
+        }
+        little.push(4);
+        big.push(4);
+        for index in 0..4i32 {
+            little.extend(&index.to_le_bytes());
+            big.extend(&index.to_be_bytes());
+        }
+
+        for contents in &[ascii, little, big] {
+            let data = parse(contents).unwrap();
+            assert_eq!(data.positions[2], Vec3::new(1.0, 1.0, 0.0));
+            assert_eq!(data.uvs[3], Vec2::new(0.0, 1.0));
+            assert_eq!(data.colors[1], Vec3::new(1.0, 0.0, 0.2));
+            assert!(data.normals.is_empty());
+            assert_eq!(data.indices, vec![0, 1, 2, 0, 2, 3]);
+        }
+
+        // the face refers to a vertex that does not exist
+        let mut truncated = header("ascii").replace("element vertex 4", "element vertex 3").into_bytes();
+        truncated.extend(b"0 0 0 0 0 0 0 0\n1 0 0 0 0 0 0 0\n1 1 0 0 0 0 0 0\n4 0 1 2 3\n");
+        assert!(parse(&truncated).is_err());
+
+        // negative and fractional indices, and a list count far beyond the end of the file
+        for face in &["4 0 1 -2 3\n", "4 0 1 2.5 3\n", "4294967295 0 1 2 3\n"] {
+            let mut corrupt = header("ascii").into_bytes();
+            for v in &vertices {
+                corrupt.extend(format!("{} {} {} 0 0 0 0 0\n", v[0], v[1], v[2]).bytes());
+            }
+            corrupt.extend(face.bytes());
+            assert!(parse(&corrupt).is_err());
+        }
+        assert!(parse(b"not a ply file\n").is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use std::f32;
+use std::sync::Arc;
+
+use glam::Vec3;
+
+use aabb::AABB;
+use hitable::{HitRecord, Hitable};
+use materials::Material;
+use ray::Ray;
+use sampler::Sampler;
+
+/// Quad is a parallelogram spanned by two edges from one of its corners
+///
+/// The normal of the quad is the cross product of the edges u and v,
+/// so swapping the edges flips the side a one-sided light emits from.
+#[derive(Clone)]
+pub struct Quad {
+    corner: Vec3,
+    u: Vec3,
+    v: Vec3,
+    normal: Vec3,
+    w: Vec3,
+    area: f32,
+    material: Arc<dyn Material>,
+}
+
+impl Quad {
+    pub fn new<M: Material + 'static>(corner: Vec3, u: Vec3, v: Vec3, material: M) -> Quad {
+        Quad::from_box(corner, u, v, Arc::new(material))
+    }
+
+    pub fn from_box(corner: Vec3, u: Vec3, v: Vec3, material: Arc<dyn Material>) -> Quad {
+        let n = u.cross(v);
+        Quad { corner,
+               u,
+               v,
+               normal: n.normalize(),
+               w: n / n.dot(n),
+               area: n.length(),
+               material }
+    }
+
+    /// Calculate the area of the quad
+    pub fn area(&self) -> f32 {
+        self.area
+    }
+}
+
+impl Hitable for Quad {
+    /// Intersect the ray with the plane of the quad
+    ///
+    /// The hit point is then written in terms of the edges of the quad and
+    /// lies inside of the quad when both of its coordinates are within [0, 1].
+    fn hit(&self, ray: &Ray, position_min: f32, position_max: f32) -> Option<HitRecord> {
+        let denominator = self.normal.dot(ray.direction);
+
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = self.normal.dot(self.corner - ray.origin) / denominator;
+
+        if t < position_min || t > position_max {
+            return None;
+        }
+
+        let point = ray.point_at_parameter(t);
+        let planar_point = point - self.corner;
+        let alpha = self.w.dot(planar_point.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar_point));
+
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        Some(HitRecord::new(t,
+                            alpha,
+                            beta,
+                            point,
+                            self.normal,
+                            self.normal,
+                            self.material.clone()))
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+        let corners = [self.corner + self.u,
+                       self.corner + self.v,
+                       self.corner + self.u + self.v];
+        let padding = Vec3::splat(0.0001);
+
+        let minimum = corners.iter().fold(self.corner, |minimum, corner| minimum.min(*corner));
+        let maximum = corners.iter().fold(self.corner, |maximum, corner| maximum.max(*corner));
+
+        Some(AABB::from(minimum - padding, maximum + padding))
+    }
+
+    /// Convert the density of uniformly sampling the area of the quad to solid angle
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        if let Some(hit) = self.hit(&Ray::new(origin, direction, time), 0.001, f32::MAX) {
+            // rays have a unit direction so the parameter is the distance to the hit
+            let distance_squared = hit.parameter * hit.parameter;
+            let cosine = direction.normalize().dot(hit.shading_normal).abs();
+            distance_squared / (cosine * self.area)
+        } else {
+            0.0
+        }
+    }
+
+    /// Sample a direction from origin towards a uniformly chosen point on the quad
+    fn pdf_random(&self, origin: Vec3, _time: f32, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        let (u, v) = sampler.get_2d();
+        let random_point = self.corner + u * self.u + v * self.v;
+        Some(random_point - origin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use materials::Empty;
+    use sampler::IndependentSampler;
+
+    #[test]
+    fn test_quad_sampling() {
+        let quad = Quad::new(Vec3::new(0.0, 1.0, 0.0),
+                             Vec3::new(2.0, 0.0, 0.0),
+                             Vec3::new(1.0, 1.0, 1.0),
+                             Empty::new());
+        let origin = Vec3::new(1.0, 5.0, -4.0);
+        let mut sampler = IndependentSampler::new(100, 0);
+
+        assert!((quad.area() - 8.0f32.sqrt()).abs() < 1e-5);
+
+        for _ in 0..100 {
+            let direction = quad.pdf_random(origin, 0.0, &mut sampler).unwrap();
+            assert!(quad.pdf_value(origin, direction, 0.0) > 0.0);
+        }
+
+        let miss = Ray::new(origin, Vec3::new(0.0, 1.0, 0.0), 0.0);
+        assert!(quad.hit(&miss, 0.001, f32::MAX).is_none());
+    }
+}
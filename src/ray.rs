@@ -8,15 +8,28 @@ pub struct Ray {
     pub direction: Vec3,
     pub time: f32,
     pub inverse_direction: Vec3,
+    /// The random number that picks where the ray scatters inside a participating medium
+    ///
+    /// The integrator draws it from its sampler, so that volumes follow the
+    /// seed and sampling pattern of the render. Rays traced without one
+    /// hash their own origin and direction instead.
+    pub medium_sample: Option<f32>,
 }
 
 impl Ray {
     /// Create a new Ray with origin at `a` and direction towards `b`
     pub fn new(origin: Vec3, direction: Vec3, time: f32) -> Ray {
+        let direction = direction.normalize();
         Ray { origin: origin,
-              direction: direction.normalize(),
+              direction: direction,
               time: time,
-              inverse_direction: direction.reciprocal() }
+              inverse_direction: direction.reciprocal(),
+              medium_sample: None }
+    }
+
+    pub fn with_medium_sample(mut self, medium_sample: f32) -> Ray {
+        self.medium_sample = Some(medium_sample);
+        self
     }
 
     /// Find the point on the ray given the parameter of the direction vector
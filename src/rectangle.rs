@@ -12,7 +12,6 @@ use world::World;
 pub struct Rectangle {
     p0: Vec3,
     p1: Vec3,
-    material: Arc<dyn Material>,
     hitables: World,
 }
 
@@ -67,10 +66,7 @@ impl Rectangle {
                                                      p1.z(),
                                                      p0.x(),
                                                      material.clone())));
-        Rectangle { p0,
-                    p1,
-                    material,
-                    hitables }
+        Rectangle { p0, p1, hitables }
     }
 }
 
@@ -0,0 +1,201 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use glam::Vec3;
+use rayon::prelude::*;
+
+use integrator::{self, Heuristic};
+use output::{self, OutputFormat};
+use sampler::SamplerType;
+use scene::Scene;
+use utils;
+
+#[cfg(feature = "denoise")]
+use denoise;
+
+/// Settings controls the size and quality of a render
+///
+/// Light and BSDF samples are combined with the power heuristic and
+/// the samples are taken from the Owen scrambled Sobol sequence unless
+/// another heuristic or sampler is chosen. Renders with the same seed
+/// produce the same image regardless of how the pixels are scheduled.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub width: usize,
+    pub height: usize,
+    pub samples: u32,
+    pub bounces: u32,
+    pub heuristic: Heuristic,
+    pub sampler: SamplerType,
+    pub seed: u64,
+}
+
+impl Settings {
+    pub fn new(width: usize, height: usize, samples: u32, bounces: u32) -> Settings {
+        Settings { width,
+                   height,
+                   samples,
+                   bounces,
+                   heuristic: Heuristic::Power,
+                   sampler: SamplerType::Sobol,
+                   seed: 0 }
+    }
+}
+
+/// Film holds the RGB radiance of every pixel of a finished render
+///
+/// Pixels are stored row by row starting from the top left
+/// corner of the image with three floats per pixel.
+#[derive(Clone)]
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<f32>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Film {
+        Film { width,
+               height,
+               pixels: vec![0.0f32; 3 * width * height] }
+    }
+
+    /// Get the color of the pixel at column x and row y from the top of the image
+    pub fn pixel(&self, x: usize, y: usize) -> Vec3 {
+        let i = 3 * (y * self.width + x);
+        Vec3::new(self.pixels[i], self.pixels[i + 1], self.pixels[i + 2])
+    }
+
+    /// Read a film from the HDR image at the given path
+    pub fn read(path: &str) -> Result<Film, String> {
+        let (pixels, width, height) = output::read_hdr(path)?;
+        Ok(Film { width,
+                  height,
+                  pixels })
+    }
+
+    /// Write the film to the given path in the given format
+    pub fn write(&self, path: &str, format: OutputFormat) -> Result<(), String> {
+        output::write_image(path, format, &self.pixels, self.width, self.height)
+    }
+
+    /// Create a denoised copy of the film
+    #[cfg(feature = "denoise")]
+    pub fn denoise(&self) -> Film {
+        Film { width: self.width,
+               height: self.height,
+               pixels: denoise::denoise(&self.pixels, self.width, self.height) }
+    }
+}
+
+/// Renderer traces the paths of a scene into a Film
+///
+/// A progress counter can be attached to follow the number
+/// of pixels that have been rendered from another thread.
+pub struct Renderer {
+    settings: Settings,
+    progress: Option<Arc<AtomicU64>>,
+}
+
+impl Renderer {
+    pub fn new(settings: Settings) -> Renderer {
+        Renderer { settings,
+                   progress: None }
+    }
+
+    /// Count every finished pixel with the given counter
+    pub fn with_progress(mut self, counter: Arc<AtomicU64>) -> Renderer {
+        self.progress = Some(counter);
+        self
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Render the scene with the path integrator
+    pub fn render(&self, scene: &Scene) -> Film {
+        let Settings { width,
+                       height,
+                       samples,
+                       bounces,
+                       heuristic,
+                       sampler,
+                       seed, } = self.settings;
+
+        let mut film = Film::new(width, height);
+        film.pixels.par_chunks_mut(3).enumerate().for_each(|(i, pixel)| {
+            let mut color = Vec3::zero();
+
+            let x = i % width;
+            let y = height - (i / width) - 1;
+
+            let mut sampler = sampler.create(samples, seed);
+
+            (0..samples).for_each(|index| {
+                sampler.start_pixel_sample(x, y, index);
+
+                let (jitter_x, jitter_y) = sampler.get_2d();
+                let u = (x as f32 + jitter_x) / width as f32;
+                let v = (y as f32 + jitter_y) / height as f32;
+                let ray = scene.camera.get_ray(u, v, sampler.as_mut());
+                color += utils::de_nan(&integrator::render_path_integrator(ray,
+                                                            &scene.world,
+                                                            bounces,
+                                                            &scene.lights,
+                                                            heuristic,
+                                                            scene.camera.atmosphere,
+                                                            sampler.as_mut()));
+            });
+
+            color /= samples as f32;
+
+            pixel[0] = color.x();
+            pixel[1] = color.y();
+            pixel[2] = color.z();
+
+            if let Some(counter) = &self.progress {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+
+        film
+    }
+}
+
+/// Render the scene with the given settings
+pub fn render(scene: &Scene, settings: &Settings) -> Film {
+    Renderer::new(settings.clone()).render(scene)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scene;
+
+    #[test]
+    fn test_render_film() {
+        let settings = Settings::new(8, 6, 1, 2);
+        let scene = scene::three_spheres_scene(settings.width, settings.height);
+        let counter = Arc::new(AtomicU64::new(0));
+
+        let film = Renderer::new(settings).with_progress(counter.clone()).render(&scene);
+
+        assert_eq!(film.pixels.len(), 3 * 8 * 6);
+        assert_eq!(counter.load(Ordering::SeqCst), 8 * 6);
+        assert!(film.pixel(0, 0).min_element() >= 0.0);
+    }
+
+    #[test]
+    fn test_render_is_reproducible() {
+        let mut settings = Settings::new(8, 6, 2, 3);
+        settings.seed = 7;
+        let scene = scene::random_spheres_scene(settings.width, settings.height, settings.seed);
+
+        let film = render(&scene, &settings);
+        assert_eq!(film.pixels, render(&scene, &settings).pixels);
+
+        settings.seed = 8;
+        assert_ne!(film.pixels, render(&scene, &settings).pixels);
+    }
+}
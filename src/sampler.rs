@@ -0,0 +1,485 @@
+/// The largest float below one so that samples stay within [0, 1)
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+/// The first primes used as the bases of the dimensions of the Halton sequence
+const PRIMES: [u32; 64] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67,
+                           71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131, 137, 139, 149,
+                           151, 157, 163, 167, 173, 179, 181, 191, 193, 197, 199, 211, 223, 227, 229,
+                           233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307, 311];
+
+/// Sampler produces the sample values of every random decision of a path
+///
+/// Each sample of a pixel starts with start_pixel_sample. The values that
+/// follow are requested one dimension at a time, either as a single value
+/// or as a pair, in the same order for every sample of the pixel. This lets
+/// stratified and low-discrepancy samplers spread each dimension evenly
+/// over the samples of the pixel. All values are within [0, 1).
+pub trait Sampler {
+    fn samples_per_pixel(&self) -> u32;
+
+    /// Start the sample with the given index of the pixel at column x and row y
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: u32);
+
+    fn get_1d(&mut self) -> f32;
+
+    fn get_2d(&mut self) -> (f32, f32);
+}
+
+/// SamplerType selects the Sampler used to render an image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SamplerType {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl SamplerType {
+    /// Find the sampler type with the given name
+    pub fn from_name(name: &str) -> Option<SamplerType> {
+        match name.to_lowercase().as_str() {
+            "independent" => Some(SamplerType::Independent),
+            "stratified" => Some(SamplerType::Stratified),
+            "halton" => Some(SamplerType::Halton),
+            "sobol" => Some(SamplerType::Sobol),
+            _ => None,
+        }
+    }
+
+    /// Create a sampler that takes the given number of samples per pixel
+    ///
+    /// The values of every pixel sample depend only on the seed, the pixel
+    /// and the sample index, so the same seed always renders the same image.
+    pub fn create(self, samples_per_pixel: u32, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerType::Independent => Box::new(IndependentSampler::new(samples_per_pixel, seed)),
+            SamplerType::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            SamplerType::Halton => Box::new(HaltonSampler::new(samples_per_pixel, seed)),
+            SamplerType::Sobol => Box::new(SobolSampler::new(samples_per_pixel, seed)),
+        }
+    }
+}
+
+/// Scramble the bits of the value with the finalizer of MurmurHash3
+fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 33;
+    v = v.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    v ^= v >> 33;
+    v = v.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    v ^= v >> 33;
+    v
+}
+
+/// Combine two values into a single well distributed hash
+///
+/// The golden ratio keeps the hash of zeros from being zero.
+fn hash(a: u64, b: u64) -> u64 {
+    mix_bits(a ^ mix_bits(b.wrapping_add(0x9e37_79b9_7f4a_7c15)))
+}
+
+/// Convert the upper bits of the value to a float in [0, 1)
+fn to_float(bits: u32) -> f32 {
+    (bits >> 8) as f32 / (1 << 24) as f32
+}
+
+/// Hash the values into a float in [0, 1)
+///
+/// Random decisions made outside of a sampler, such as the scattering
+/// distance of rays a volume is given no sample for, hash their inputs
+/// so they are reproducible.
+pub fn hash_float(values: &[u64]) -> f32 {
+    let bits = values.iter().fold(0, |bits, value| hash(bits, *value));
+    to_float(bits as u32)
+}
+
+/// Find the element at index i of a random permutation of [0, length)
+///
+/// The permutation is chosen by the seed and computed without storing it.
+///
+/// Andrew Kensler: Correlated Multi-Jittered Sampling, Pixar Technical Memo 13-01, 2013
+fn permutation_element(mut i: u32, length: u32, seed: u32) -> u32 {
+    let mut w = length - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+
+        if i < length {
+            break;
+        }
+    }
+
+    (i.wrapping_add(seed)) % length
+}
+
+/// The state shared by the samplers: the pixel sample and the next dimension
+struct PixelSample {
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dimension: u32,
+}
+
+impl PixelSample {
+    fn new(seed: u64) -> PixelSample {
+        let mut state = PixelSample { seed,
+                                      pixel: 0,
+                                      index: 0,
+                                      dimension: 0 };
+        state.start(0, 0, 0);
+        state
+    }
+
+    fn start(&mut self, x: usize, y: usize, index: u32) {
+        self.pixel = hash(self.seed, hash(x as u64, y as u64));
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    /// Take the next dimension and a hash that is unique to the pixel and that dimension
+    fn next_dimension(&mut self) -> (u32, u64) {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        (dimension, hash(self.pixel, u64::from(dimension)))
+    }
+
+    /// Hash the pixel, sample and dimension into independent random values
+    fn random(&self, dimension_hash: u64) -> u64 {
+        hash(dimension_hash, u64::from(self.index))
+    }
+}
+
+/// IndependentSampler takes every value uniformly at random
+pub struct IndependentSampler {
+    samples_per_pixel: u32,
+    state: PixelSample,
+}
+
+impl IndependentSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> IndependentSampler {
+        IndependentSampler { samples_per_pixel,
+                             state: PixelSample::new(seed) }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.state.start(x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let (_, dimension_hash) = self.state.next_dimension();
+        to_float(self.state.random(dimension_hash) as u32)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let (_, dimension_hash) = self.state.next_dimension();
+        let bits = self.state.random(dimension_hash);
+        (to_float(bits as u32), to_float((bits >> 32) as u32))
+    }
+}
+
+/// StratifiedSampler places each sample of a pixel in its own stratum
+///
+/// Single values split [0, 1) into one stratum per sample. Pairs split the
+/// square into a grid with as many cells as samples, as close to square as
+/// the number of samples allows. The samples are jittered within their
+/// stratum and every dimension visits the strata in a different order.
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    columns: u32,
+    rows: u32,
+    state: PixelSample,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> StratifiedSampler {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        // the largest divisor of the number of samples that is at most its square root
+        let square_root = (samples_per_pixel as f32).sqrt() as u32;
+        let columns = (1..=square_root).rev()
+                                       .find(|c| (samples_per_pixel / c) * c == samples_per_pixel)
+                                       .unwrap_or(1);
+
+        StratifiedSampler { samples_per_pixel,
+                            columns,
+                            rows: samples_per_pixel / columns,
+                            state: PixelSample::new(seed) }
+    }
+
+    /// The stratum of the current sample in the given dimension
+    fn stratum(&self, dimension_hash: u64) -> u32 {
+        permutation_element(self.state.index % self.samples_per_pixel,
+                            self.samples_per_pixel,
+                            dimension_hash as u32)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.state.start(x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let (_, dimension_hash) = self.state.next_dimension();
+        let jitter = to_float(self.state.random(dimension_hash) as u32);
+
+        ((self.stratum(dimension_hash) as f32 + jitter) / self.samples_per_pixel as f32)
+            .min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let (_, dimension_hash) = self.state.next_dimension();
+        let bits = self.state.random(dimension_hash);
+        let stratum = self.stratum(dimension_hash);
+
+        let x = (stratum % self.columns) as f32 + to_float(bits as u32);
+        let y = (stratum / self.columns) as f32 + to_float((bits >> 32) as u32);
+
+        ((x / self.columns as f32).min(ONE_MINUS_EPSILON),
+         (y / self.rows as f32).min(ONE_MINUS_EPSILON))
+    }
+}
+
+/// Compute the radical inverse of index in the given base with permuted digits
+///
+/// Every digit is permuted with its own random permutation chosen by the
+/// seed, which decorrelates the pixels while keeping the sequence well
+/// distributed. Digits are generated until they no longer change the result
+/// so that the permutation of the trailing zero digits is included.
+fn scrambled_radical_inverse(base: u32, mut index: u64, seed: u64) -> f32 {
+    let inverse_base = 1.0 / f64::from(base);
+    let mut inverse_base_power = 1.0;
+    let mut radical_inverse = 0.0;
+    let mut digit_index = 0;
+
+    while 1.0 - f64::from(base - 1) * inverse_base_power < 1.0 {
+        let next = index / u64::from(base);
+        let digit = (index - next * u64::from(base)) as u32;
+        let digit_seed = mix_bits(seed ^ digit_index) as u32;
+
+        inverse_base_power *= inverse_base;
+        radical_inverse += f64::from(permutation_element(digit, base, digit_seed)) * inverse_base_power;
+        digit_index += 1;
+        index = next;
+    }
+
+    (radical_inverse as f32).min(ONE_MINUS_EPSILON)
+}
+
+/// HaltonSampler takes values from the Halton sequence
+///
+/// Every dimension is the radical inverse of the sample index in the next
+/// prime base. The digits are randomly permuted for every pixel. Dimensions
+/// beyond the table of primes fall back to independent random values.
+pub struct HaltonSampler {
+    samples_per_pixel: u32,
+    prime_index: usize,
+    state: PixelSample,
+}
+
+impl HaltonSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> HaltonSampler {
+        HaltonSampler { samples_per_pixel,
+                        prime_index: 0,
+                        state: PixelSample::new(seed) }
+    }
+
+    fn sample(&mut self) -> f32 {
+        let (_, dimension_hash) = self.state.next_dimension();
+
+        match PRIMES.get(self.prime_index) {
+            Some(&base) => {
+                self.prime_index += 1;
+                scrambled_radical_inverse(base, u64::from(self.state.index), dimension_hash)
+            }
+            None => to_float(self.state.random(dimension_hash) as u32),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.state.start(x, y, index);
+        self.prime_index = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.sample()
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.sample(), self.sample())
+    }
+}
+
+/// Compute a point of the first two dimensions of the Sobol sequence
+///
+/// The first dimension is the van der Corput sequence in base two and the
+/// second uses the direction numbers of the primitive polynomial x + 1.
+fn sobol_2d(index: u32) -> (u32, u32) {
+    let mut x = 0;
+    let mut y = 0;
+    let mut direction: u32 = 1 << 31;
+
+    for bit in 0..32 {
+        if (index >> bit) & 1 == 1 {
+            x ^= 1 << (31 - bit);
+            y ^= direction;
+        }
+        direction ^= direction >> 1;
+    }
+
+    (x, y)
+}
+
+/// Owen scramble the bits of the value with a hash
+///
+/// Brent Burley: Practical Hash-based Owen Scrambling,
+/// Journal of Computer Graphics Techniques Vol. 9, No. 4, 2020 http://jcgt.org
+fn owen_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x.reverse_bits()
+}
+
+/// SobolSampler takes values from the Owen scrambled Sobol sequence
+///
+/// Every dimension is a point of the first two dimensions of the Sobol sequence
+/// with its own shuffled order of the sample indices and its own Owen
+/// scrambling, which keeps each pair well stratified over the samples of a pixel
+/// while decorrelating the dimensions and the pixels from each other. The
+/// sequence is best used with a number of samples per pixel that is a power of two.
+pub struct SobolSampler {
+    samples_per_pixel: u32,
+    state: PixelSample,
+}
+
+impl SobolSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> SobolSampler {
+        SobolSampler { samples_per_pixel,
+                       state: PixelSample::new(seed) }
+    }
+
+    fn sample(&mut self) -> (u32, u32) {
+        let (_, dimension_hash) = self.state.next_dimension();
+        let index = owen_scramble(self.state.index, dimension_hash as u32);
+        let (x, y) = sobol_2d(index);
+
+        (owen_scramble(x, (dimension_hash >> 32) as u32),
+         owen_scramble(y, mix_bits(dimension_hash) as u32))
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.state.start(x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        to_float(self.sample().0)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let (x, y) = self.sample();
+        (to_float(x), to_float(y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLERS: [SamplerType; 4] = [SamplerType::Independent,
+                                        SamplerType::Stratified,
+                                        SamplerType::Halton,
+                                        SamplerType::Sobol];
+
+    #[test]
+    fn test_sample_range() {
+        for sampler_type in &SAMPLERS {
+            let mut sampler = sampler_type.create(16, 0);
+            let mut sum = 0.0;
+
+            for index in 0..sampler.samples_per_pixel() {
+                sampler.start_pixel_sample(3, 7, index);
+                for _ in 0..80 {
+                    let (u, v) = sampler.get_2d();
+                    let w = sampler.get_1d();
+
+                    assert!(u >= 0.0 && u < 1.0 && v >= 0.0 && v < 1.0 && w >= 0.0 && w < 1.0);
+                    sum += u + v + w;
+                }
+            }
+
+            let mean = sum / (16.0 * 80.0 * 3.0);
+            assert!((mean - 0.5).abs() < 0.05, "{:?} has mean {}", sampler_type, mean);
+        }
+    }
+
+    #[test]
+    fn test_sample_stratification() {
+        // every sampler but the independent one puts exactly one of the 16 samples
+        // of each dimension into each interval of width 1/16
+        for sampler_type in &SAMPLERS[1..] {
+            let mut sampler = sampler_type.create(16, 0);
+
+            for dimension in 0..4 {
+                let mut strata = [0; 16];
+
+                for index in 0..16 {
+                    sampler.start_pixel_sample(1, 2, index);
+                    for _ in 0..dimension {
+                        sampler.get_1d();
+                    }
+                    strata[(sampler.get_1d() * 16.0) as usize] += 1;
+                }
+
+                if *sampler_type != SamplerType::Halton || dimension == 0 {
+                    assert_eq!(strata, [1; 16], "{:?} in dimension {}", sampler_type, dimension);
+                }
+            }
+        }
+    }
+}
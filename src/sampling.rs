@@ -1,25 +1,17 @@
-use rand::rngs::ThreadRng;
-use rand::Rng;
 use std::f32::consts::PI;
 
 use glam::Vec3;
 
-pub fn cosine_sample_hemisphere(rng: &mut ThreadRng) -> Vec3 {
-    let r1 = rng.gen::<f32>();
-    let r2 = rng.gen::<f32>();
-
+pub fn cosine_sample_hemisphere(r1: f32, r2: f32) -> Vec3 {
     let phi = 2.0 * PI * r1;
 
-    let x = phi.cos() * 2.0 * r2.sqrt();
-    let y = phi.sin() * 2.0 * r2.sqrt();
+    let x = phi.cos() * r2.sqrt();
+    let y = phi.sin() * r2.sqrt();
     let z = (1.0 - r2).sqrt();
     Vec3::new(x, y, z)
 }
 
-pub fn uniform_sample_hemisphere(rng: &mut ThreadRng) -> Vec3 {
-    let u = rng.gen::<f32>();
-    let v = rng.gen::<f32>();
-
+pub fn uniform_sample_hemisphere(u: f32, v: f32) -> Vec3 {
     let z = u;
     let r = (1.0 - z * z).sqrt();
     let phi = 2.0 * PI * v;
@@ -30,10 +22,22 @@ pub fn uniform_sample_hemisphere(rng: &mut ThreadRng) -> Vec3 {
     Vec3::new(x, y, z)
 }
 
-pub fn uniform_sample_sphere(rng: &mut ThreadRng) -> Vec3 {
-    let u = rng.gen::<f32>();
-    let v = rng.gen::<f32>();
+/// Sample a direction uniformly within the cone around +Z
+///
+/// The cone contains every direction whose angle with +Z has
+/// a cosine of at least cos_theta_max.
+pub fn uniform_sample_cone(u: f32, v: f32, cos_theta_max: f32) -> Vec3 {
+    let z = 1.0 - u * (1.0 - cos_theta_max);
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+
+    let x = r * phi.cos();
+    let y = r * phi.sin();
+
+    Vec3::new(x, y, z)
+}
 
+pub fn uniform_sample_sphere(u: f32, v: f32) -> Vec3 {
     let z = 1.0 - (2.0 * u);
     let r = (1.0 - z * z).sqrt();
     let phi = 2.0 * PI * v;
@@ -43,3 +47,27 @@ pub fn uniform_sample_sphere(rng: &mut ThreadRng) -> Vec3 {
 
     Vec3::new(x, y, z)
 }
+
+/// Map a point of the unit square to the unit disk
+///
+/// The concentric mapping keeps points that are well spread
+/// over the square well spread over the disk.
+///
+/// Peter Shirley, Kenneth Chiu: A Low Distortion Map Between Disk and Square,
+/// Journal of Graphics Tools Vol. 2, No. 3, 1997
+pub fn concentric_sample_disk(u: f32, v: f32) -> (f32, f32) {
+    let x = 2.0 * u - 1.0;
+    let y = 2.0 * v - 1.0;
+
+    if x == 0.0 && y == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let (r, theta) = if x.abs() > y.abs() {
+        (x, (PI / 4.0) * (y / x))
+    } else {
+        (y, (PI / 2.0) - (PI / 4.0) * (x / y))
+    };
+
+    (r * theta.cos(), r * theta.sin())
+}
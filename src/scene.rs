@@ -2,26 +2,75 @@ use std::f32;
 use std::sync::Arc;
 
 use glam::Vec3;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 use bvh::BVH;
 use camera::Camera;
 use hitable::FlipNormals;
-use materials::{Diffuse, Empty, Light, Reflective, Refractive};
+use lights::{LightList, LightSelection};
+use materials::{Diffuse, Light, Reflective, Refractive};
+use obj;
 use plane::{Axis, Plane};
 use rectangle::Rectangle;
 use sphere::Sphere;
 use texture::{ConstantTexture, ImageTexture};
 use transformations::{Rotate, Translate};
-use triangle::TriangleMesh;
+use triangle::Normals;
+use utils;
 use volume::Volume;
 use world::World;
 
-pub fn three_spheres_scene(width: usize, height: usize) -> (String, Camera, BVH, Plane) {
+/// A Scene holds everything needed to render an image: the camera,
+/// the objects in the world, and the light sources to importance sample.
+pub struct Scene {
+    pub name: String,
+    pub camera: Camera,
+    pub world: BVH,
+    pub lights: LightList,
+}
+
+impl Scene {
+    pub fn new(name: &str, camera: Camera, world: BVH, lights: LightList) -> Scene {
+        Scene { name: String::from(name),
+                camera,
+                world,
+                lights }
+    }
+}
+
+/// The names of the scenes that can be rendered with from_name
+pub const SCENES: [&str; 7] = ["three_spheres",
+                               "random_spheres",
+                               "earth",
+                               "motion",
+                               "simple_light",
+                               "cornell_box",
+                               "spheres_in_box"];
+
+/// Build the scene with the given name, one of the names listed in SCENES
+///
+/// Scenes with randomly placed objects are generated from the seed
+/// so that the same seed always builds the same scene.
+pub fn from_name(name: &str, width: usize, height: usize, seed: u64) -> Option<Scene> {
+    match name {
+        "three_spheres" => Some(three_spheres_scene(width, height)),
+        "random_spheres" => Some(random_spheres_scene(width, height, seed)),
+        "earth" => Some(earth_scene(width, height)),
+        "motion" => Some(motion_scene(width, height, seed)),
+        "simple_light" => Some(simple_light_scene(width, height)),
+        "cornell_box" => Some(cornell_box_scene(width, height)),
+        "spheres_in_box" => Some(spheres_in_box_scene(width, height, seed)),
+        _ => None,
+    }
+}
+
+pub fn three_spheres_scene(width: usize, height: usize) -> Scene {
     let origin = Vec3::new(0.0, 3.0, 6.0);
     let lookat = Vec3::new(0.0, 0.0, -1.5);
     let view = Vec3::new(0.0, 1.0, 0.0);
     let fov = 20.0;
-    let aspect_ratio = (width / height) as f32;
+    let aspect_ratio = width as f32 / height as f32;
     let aperture = 0.1;
     let focus_distance = 10.0;
     let time0 = 0.0;
@@ -71,17 +120,17 @@ pub fn three_spheres_scene(width: usize, height: usize) -> (String, Camera, BVH,
 
     let bvh = BVH::new(&mut world.objects, 0.0, 1.0);
 
-    let light = Plane::new(Axis::XY, 0.0, 0.0, 0.0, 0.0, 0.0, Empty::new());
+    let lights = LightList::new(LightSelection::Power);
 
-    (String::from("Three Spheres"), camera, bvh, light)
+    Scene::new("Three Spheres", camera, bvh, lights)
 }
 
-pub fn random_spheres_scene(width: usize, height: usize) -> (String, Camera, BVH, Plane) {
+pub fn random_spheres_scene(width: usize, height: usize, seed: u64) -> Scene {
     let origin = Vec3::new(13.0, 2.0, 3.0);
     let lookat = Vec3::new(0.0, 0.0, 0.0);
     let view = Vec3::new(0.0, 1.0, 0.0);
     let fov = 20.0;
-    let aspect_ratio = (width / height) as f32;
+    let aspect_ratio = width as f32 / height as f32;
     let aperture = 0.1;
     let focus_distance = 10.0;
     let time0 = 0.0;
@@ -100,6 +149,7 @@ pub fn random_spheres_scene(width: usize, height: usize) -> (String, Camera, BVH
                              atmosphere);
 
     let mut world = World::new();
+    let mut rng = StdRng::seed_from_u64(seed);
 
     world.add(Sphere::new(Vec3::new(0.0, -1000.0, 0.0),
                           Vec3::new(0.0, -1000.0, 0.0),
@@ -110,22 +160,22 @@ pub fn random_spheres_scene(width: usize, height: usize) -> (String, Camera, BVH
 
     for a in -11..11 {
         for b in -11..11 {
-            let material = rand::random::<f32>();
-            let center: Vec3 = Vec3::new(a as f32 + 0.9 * rand::random::<f32>(),
+            let material = rng.gen::<f32>();
+            let center: Vec3 = Vec3::new(a as f32 + 0.9 * rng.gen::<f32>(),
                                          0.2,
-                                         b as f32 + 0.9 * rand::random::<f32>());
+                                         b as f32 + 0.9 * rng.gen::<f32>());
 
             if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
                 if material < 0.75 {
                     world.add(Sphere::new(center,
                                      center,
                                      0.2,
-                                     Diffuse::new(ConstantTexture::new(rand::random::<f32>()
-                                                                       * rand::random::<f32>(),
-                                                                       rand::random::<f32>()
-                                                                       * rand::random::<f32>(),
-                                                                       rand::random::<f32>()
-                                                                       * rand::random::<f32>()),
+                                     Diffuse::new(ConstantTexture::new(rng.gen::<f32>()
+                                                                       * rng.gen::<f32>(),
+                                                                       rng.gen::<f32>()
+                                                                       * rng.gen::<f32>(),
+                                                                       rng.gen::<f32>()
+                                                                       * rng.gen::<f32>()),
                                                   0.0),
                                      0.0,
                                      1.0));
@@ -135,14 +185,14 @@ pub fn random_spheres_scene(width: usize, height: usize) -> (String, Camera, BVH
                                           0.2,
                                           Reflective::new(Vec3::new(0.5
                                                                     * (1.0
-                                                                       * rand::random::<f32>()),
+                                                                       * rng.gen::<f32>()),
                                                                     0.5
                                                                     * (1.0
-                                                                       * rand::random::<f32>()),
+                                                                       * rng.gen::<f32>()),
                                                                     0.5
                                                                     * (1.0
-                                                                       * rand::random::<f32>())),
-                                                          0.5 * rand::random::<f32>()),
+                                                                       * rng.gen::<f32>())),
+                                                          0.5 * rng.gen::<f32>()),
                                           0.0,
                                           1.0));
                 } else {
@@ -184,22 +234,22 @@ pub fn random_spheres_scene(width: usize, height: usize) -> (String, Camera, BVH
 
     let bvh = BVH::new(&mut world.objects, 0.0, 1.0);
 
-    let light = Plane::new(Axis::XY, 0.0, 0.0, 0.0, 0.0, 0.0, Empty::new());
+    let lights = LightList::new(LightSelection::Power);
 
-    (String::from("Random Spheres"), camera, bvh, light)
+    Scene::new("Random Spheres", camera, bvh, lights)
 }
 
-pub fn earth_scene(width: usize, height: usize) -> (String, Camera, World, Plane) {
+pub fn earth_scene(width: usize, height: usize) -> Scene {
     let origin = Vec3::new(13.0, 2.0, 3.0);
     let lookat = Vec3::new(0.0, 0.0, 0.0);
     let view = Vec3::new(0.0, 1.0, 0.0);
     let fov = 20.0;
-    let aspect_ratio = (width / height) as f32;
+    let aspect_ratio = width as f32 / height as f32;
     let aperture = 0.1;
     let focus_distance = 10.0;
     let time0 = 0.0;
     let time1 = 1.0;
-    let atmosphere = false;
+    let atmosphere = true;
 
     let camera = Camera::new(origin,
                              lookat,
@@ -221,17 +271,19 @@ pub fn earth_scene(width: usize, height: usize) -> (String, Camera, World, Plane
                           0.0,
                           1.0));
 
-    let light = Plane::new(Axis::XY, 0.0, 0.0, 0.0, 0.0, 0.0, Empty::new());
+    let bvh = BVH::new(&mut world.objects, 0.0, 1.0);
 
-    (String::from("Earth"), camera, world, light)
+    let lights = LightList::new(LightSelection::Power);
+
+    Scene::new("Earth", camera, bvh, lights)
 }
 
-pub fn motion_scene(width: usize, height: usize) -> (String, Camera, BVH, Plane) {
+pub fn motion_scene(width: usize, height: usize, seed: u64) -> Scene {
     let origin = Vec3::new(13.0, 2.0, 3.0);
     let lookat = Vec3::new(0.0, 0.0, 0.0);
     let view = Vec3::new(0.0, 1.0, 0.0);
     let fov = 20.0;
-    let aspect_ratio = (width / height) as f32;
+    let aspect_ratio = width as f32 / height as f32;
     let aperture = 0.1;
     let focus_distance = 10.0;
     let time0 = 0.0;
@@ -250,6 +302,7 @@ pub fn motion_scene(width: usize, height: usize) -> (String, Camera, BVH, Plane)
                              atmosphere);
 
     let mut world = World::new();
+    let mut rng = StdRng::seed_from_u64(seed);
 
     world.add(Sphere::new(Vec3::new(0.0, -1000.0, 0.0),
                           Vec3::new(0.0, -1000.0, 0.0),
@@ -258,19 +311,19 @@ pub fn motion_scene(width: usize, height: usize) -> (String, Camera, BVH, Plane)
                           0.0,
                           1.0));
 
-    let center: Vec3 = Vec3::new(0.9 * rand::random::<f32>(),
+    let center: Vec3 = Vec3::new(0.9 * rng.gen::<f32>(),
                                  0.2,
-                                 0.9 * rand::random::<f32>());
+                                 0.9 * rng.gen::<f32>());
 
     world.add(Sphere::new(center,
-                          center + Vec3::new(0.0, 0.5 * rand::random::<f32>(), 0.0),
+                          center + Vec3::new(0.0, 0.5 * rng.gen::<f32>(), 0.0),
                           0.2,
-                          Diffuse::new(ConstantTexture::new(rand::random::<f32>()
-                                                            * rand::random::<f32>(),
-                                                            rand::random::<f32>()
-                                                            * rand::random::<f32>(),
-                                                            rand::random::<f32>()
-                                                            * rand::random::<f32>()),
+                          Diffuse::new(ConstantTexture::new(rng.gen::<f32>()
+                                                            * rng.gen::<f32>(),
+                                                            rng.gen::<f32>()
+                                                            * rng.gen::<f32>(),
+                                                            rng.gen::<f32>()
+                                                            * rng.gen::<f32>()),
                                        0.0),
                           0.0,
                           1.0));
@@ -284,17 +337,17 @@ pub fn motion_scene(width: usize, height: usize) -> (String, Camera, BVH, Plane)
 
     let bvh = BVH::new(&mut world.objects, 0.0, 1.0);
 
-    let light = Plane::new(Axis::XY, 0.0, 0.0, 0.0, 0.0, 0.0, Empty::new());
+    let lights = LightList::new(LightSelection::Power);
 
-    (String::from("Motion Blur"), camera, bvh, light)
+    Scene::new("Motion Blur", camera, bvh, lights)
 }
 
-pub fn simple_light_scene(width: usize, height: usize) -> (String, Camera, BVH, Plane) {
+pub fn simple_light_scene(width: usize, height: usize) -> Scene {
     let origin = Vec3::new(13.0, 3.0, 3.0);
     let lookat = Vec3::new(0.0, 0.0, 0.0);
     let view = Vec3::new(0.0, 1.0, 0.0);
     let fov = 50.0;
-    let aspect_ratio = (width / height) as f32;
+    let aspect_ratio = width as f32 / height as f32;
     let aperture = 0.1;
     let focus_distance = 10.0;
     let time0 = 0.0;
@@ -321,38 +374,48 @@ pub fn simple_light_scene(width: usize, height: usize) -> (String, Camera, BVH,
                           0.0,
                           1.0));
 
-    world.add(Translate::new(Vec3::new(0.0, 2.0, 0.0), Rotate::new(90.0, TriangleMesh::from("suzanne.obj",
-                          Arc::new(Diffuse::new(ConstantTexture::new(1.0, 0.0, 0.0), 0.0))))));
-
-    world.add(Sphere::new(Vec3::new(0.0, 7.0, 0.0),
-                          Vec3::new(0.0, 7.0, 0.0),
-                          2.0,
-                          Light::new(ConstantTexture::new(4.0, 4.0, 4.0)),
-                          0.0,
-                          1.0));
-
-    world.add(Plane::new(Axis::XY,
-                         3.0,
-                         5.0,
-                         1.0,
-                         3.0,
-                         -2.0,
-                         Light::new(ConstantTexture::new(4.0, 4.0, 4.0))));
+    let suzanne = obj::load("suzanne.obj",
+                            Arc::new(Diffuse::new(ConstantTexture::new(1.0, 0.0, 0.0), 0.0)),
+                            Normals::Smooth).unwrap();
+    world.add(Translate::new(Vec3::new(0.0, 2.0, 0.0), Rotate::new(90.0, suzanne)));
+
+    let emission = Vec3::new(4.0, 4.0, 4.0);
+    let light_sphere = Sphere::new(Vec3::new(0.0, 7.0, 0.0),
+                                   Vec3::new(0.0, 7.0, 0.0),
+                                   2.0,
+                                   Light::new(ConstantTexture::new(emission.x(),
+                                                                   emission.y(),
+                                                                   emission.z())),
+                                   0.0,
+                                   1.0);
+    world.add(light_sphere.clone());
+
+    let light_wall = Plane::new(Axis::XY,
+                                3.0,
+                                5.0,
+                                1.0,
+                                3.0,
+                                -2.0,
+                                Light::new(ConstantTexture::new(emission.x(),
+                                                                emission.y(),
+                                                                emission.z())));
+    world.add(light_wall.clone());
 
     let bvh = BVH::new(&mut world.objects, 0.0, 1.0);
 
-    let light = Light::new(ConstantTexture::new(0.0, 0.0, 0.0));
-    let light_shape = Plane::new(Axis::XZ, 3.0, 5.0, 1.0, 3.0, -2.0, light);
+    let mut lights = LightList::new(LightSelection::Power);
+    lights.add(light_sphere.clone(), utils::luminance(&emission) * light_sphere.area());
+    lights.add(light_wall.clone(), utils::luminance(&emission) * light_wall.area());
 
-    (String::from("Simple Light"), camera, bvh, light_shape)
+    Scene::new("Simple Light", camera, bvh, lights)
 }
 
-pub fn cornell_box_scene(width: usize, height: usize) -> (String, Camera, BVH, Plane) {
+pub fn cornell_box_scene(width: usize, height: usize) -> Scene {
     let origin = Vec3::new(278.0, 278.0, -800.0);
     let lookat = Vec3::new(278.0, 278.0, 0.0);
     let view = Vec3::new(0.0, 1.0, 0.0);
     let fov = 40.0;
-    let aspect_ratio = (width / height) as f32;
+    let aspect_ratio = width as f32 / height as f32;
     let aperture = 0.0;
     let focus_distance = 10.0;
     let time0 = 0.0;
@@ -376,14 +439,18 @@ pub fn cornell_box_scene(width: usize, height: usize) -> (String, Camera, BVH, P
     let red = Diffuse::new(ConstantTexture::new(0.65, 0.05, 0.05), roughness);
     let green = Diffuse::new(ConstantTexture::new(0.12, 0.45, 0.15), roughness);
     let white = Diffuse::new(ConstantTexture::new(0.73, 0.73, 0.73), roughness);
-    let light = Light::new(ConstantTexture::new(35.0, 20.2, 5.6));
+    let emission = Vec3::new(35.0, 20.2, 5.6);
+    let light = Light::new(ConstantTexture::new(emission.x(), emission.y(), emission.z()));
+    let light_shape = Plane::new(Axis::XZ, 213.0, 343.0, 227.0, 332.0, 554.0, light);
 
     // add the walls of the cornell box to the world
     world.add(FlipNormals::of(Plane::new(Axis::YZ, 0.0, 555.0, 0.0, 555.0, 555.0, red)));
 
     world.add(Plane::new(Axis::YZ, 0.0, 555.0, 0.0, 555.0, 0.0, green));
 
-    world.add(FlipNormals::of(Plane::new(Axis::XZ, 213.0, 343.0, 227.0, 332.0, 554.0, light)));
+    let light_power = utils::luminance(&emission) * light_shape.area();
+    let light_shape = FlipNormals::of(light_shape);
+    world.add(light_shape.clone());
 
     world.add(FlipNormals::of(Plane::new(Axis::XZ, 0.0, 555.0, 0.0, 555.0, 555.0, white.clone())));
 
@@ -405,18 +472,18 @@ pub fn cornell_box_scene(width: usize, height: usize) -> (String, Camera, BVH, P
 
     let bvh = BVH::new(&mut world.objects, 0.0, 1.0);
 
-    let light = Light::new(ConstantTexture::new(0.0, 0.0, 0.0));
-    let light_shape = Plane::new(Axis::XZ, 213.0, 343.0, 227.0, 332.0, 554.0, light);
+    let mut lights = LightList::new(LightSelection::Power);
+    lights.add(light_shape, light_power);
 
-    (String::from("Cornell Box"), camera, bvh, light_shape)
+    Scene::new("Cornell Box", camera, bvh, lights)
 }
 
-pub fn spheres_in_box_scene(width: usize, height: usize) -> (String, Camera, BVH, Plane) {
+pub fn spheres_in_box_scene(width: usize, height: usize, seed: u64) -> Scene {
     let origin = Vec3::new(478.0, 278.0, -600.0);
     let lookat = Vec3::new(278.0, 278.0, 0.0);
     let view = Vec3::new(0.0, 1.0, 0.0);
     let fov = 40.0;
-    let aspect_ratio = (width / height) as f32;
+    let aspect_ratio = width as f32 / height as f32;
     let aperture = 0.0;
     let focus_distance = 10.0;
     let time0 = 0.0;
@@ -435,10 +502,12 @@ pub fn spheres_in_box_scene(width: usize, height: usize) -> (String, Camera, BVH
                              atmosphere);
 
     let mut world = World::new();
+    let mut rng = StdRng::seed_from_u64(seed);
 
     let white = Diffuse::new(ConstantTexture::new(0.73, 0.73, 0.73), 0.0);
     let orange = Diffuse::new(ConstantTexture::new(1.0, 0.10, 0.0), 0.0);
-    let light = Light::new(ConstantTexture::new(7.0, 7.0, 7.0));
+    let emission = Vec3::new(7.0, 7.0, 7.0);
+    let light = Light::new(ConstantTexture::new(emission.x(), emission.y(), emission.z()));
     let ground = Diffuse::new(ConstantTexture::new(0.48, 0.83, 0.53), 0.0);
 
     let number_of_boxes = 20;
@@ -447,12 +516,16 @@ pub fn spheres_in_box_scene(width: usize, height: usize) -> (String, Camera, BVH
         for j in 0..number_of_boxes {
             let w = 100.0;
             let p0 = Vec3::new(-1000.0 + i as f32 * w, 0.0, -1000.0 + j as f32 * w);
-            let p1 = p0 + Vec3::new(w, 100.0 * (rand::random::<f32>() + 0.01), w);
+            let p1 = p0 + Vec3::new(w, 100.0 * (rng.gen::<f32>() + 0.01), w);
             world.add(Rectangle::new(p0, p1, Arc::new(ground.clone())));
         }
     }
 
-    world.add(Plane::new(Axis::XZ, 123.0, 423.0, 147.0, 412.0, 554.0, light));
+    let light_shape = Plane::new(Axis::XZ, 123.0, 423.0, 147.0, 412.0, 554.0, light);
+    let light_power = utils::luminance(&emission) * light_shape.area();
+    // the light faces down into the scene
+    let light_shape = FlipNormals::of(light_shape);
+    world.add(light_shape.clone());
 
     world.add(Sphere::new(Vec3::new(400.0, 400.0, 200.0),
                           Vec3::new(430.0, 400.0, 200.0),
@@ -511,9 +584,9 @@ pub fn spheres_in_box_scene(width: usize, height: usize) -> (String, Camera, BVH
 
     let number_of_spheres = 1000;
     for _ in 0..number_of_spheres {
-        let center = Vec3::new(165.0 * rand::random::<f32>(),
-                               165.0 * rand::random::<f32>(),
-                               165.0 * rand::random::<f32>());
+        let center = Vec3::new(165.0 * rng.gen::<f32>(),
+                               165.0 * rng.gen::<f32>(),
+                               165.0 * rng.gen::<f32>());
 
         let sphere = Sphere::new(center, center, 10.0, white.clone(), 0.0, 1.0);
 
@@ -522,8 +595,8 @@ pub fn spheres_in_box_scene(width: usize, height: usize) -> (String, Camera, BVH
 
     let bvh = BVH::new(&mut world.objects, 0.0, 1.0);
 
-    let light = Light::new(ConstantTexture::new(0.0, 0.0, 0.0));
-    let light_shape = Plane::new(Axis::XZ, 123.0, 423.0, 147.0, 412.0, 554.0, light);
+    let mut lights = LightList::new(LightSelection::Power);
+    lights.add(light_shape, light_power);
 
-    (String::from("Spheres in Box"), camera, bvh, light_shape)
+    Scene::new("Spheres in Box", camera, bvh, lights)
 }
@@ -0,0 +1,850 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use glam::{Mat4, Quat, Vec3};
+use serde::Deserialize;
+use toml;
+
+use bvh::BVH;
+use camera::Camera;
+use hitable::{FlipNormals, Hitable};
+use lights::{LightList, LightSelection};
+use materials::{absorption_at_distance, Coated, Conductor, Diffuse, Empty, Isotropic, Light, Material,
+                Principled, Reflective, Refractive, RoughDielectric};
+use obj;
+use plane::{Axis, Plane};
+use ply;
+use quad::Quad;
+use rectangle::Rectangle;
+use scene::Scene;
+use sphere::Sphere;
+use texture::{ConstantTexture, ImageTexture, Texture};
+use transformations::{rotation, scaling, translation, AnimatedTransform, Keyframe, Transform};
+use triangle::{Normals, TriangleMesh};
+use utils::luminance;
+use volume::Volume;
+
+/// SceneError describes why a scene file could not be loaded
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(e) => write!(f, "could not read scene file: {}", e),
+            SceneError::Parse(e) => write!(f, "could not parse scene file: {}", e),
+            SceneError::Invalid(message) => write!(f, "invalid scene: {}", message),
+        }
+    }
+}
+
+impl Error for SceneError {}
+
+impl From<io::Error> for SceneError {
+    fn from(error: io::Error) -> SceneError {
+        SceneError::Io(error)
+    }
+}
+
+impl From<toml::de::Error> for SceneError {
+    fn from(error: toml::de::Error) -> SceneError {
+        SceneError::Parse(error)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDescription {
+    name: Option<String>,
+    #[serde(default = "default_light_selection")]
+    light_selection: SelectionDescription,
+    camera: CameraDescription,
+    #[serde(default)]
+    textures: HashMap<String, TextureDescription>,
+    #[serde(default)]
+    materials: HashMap<String, MaterialDescription>,
+    #[serde(default)]
+    objects: Vec<ObjectDescription>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDescription {
+    origin: [f32; 3],
+    lookat: [f32; 3],
+    #[serde(default = "default_view")]
+    view: [f32; 3],
+    fov: f32,
+    #[serde(default)]
+    aperture: f32,
+    #[serde(default = "default_focus_distance")]
+    focus_distance: f32,
+    #[serde(default)]
+    start_time: f32,
+    #[serde(default = "default_end_time")]
+    end_time: f32,
+    #[serde(default)]
+    atmosphere: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SelectionDescription {
+    Uniform,
+    Power,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDescription {
+    Constant { color: [f32; 3] },
+    Image { path: String },
+}
+
+/// A color is either an RGB triple or the name of a texture
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorDescription {
+    Rgb([f32; 3]),
+    Texture(String),
+}
+
+/// A value is either a number or the name of a texture whose first channel holds it
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ValueDescription {
+    Value(f32),
+    Texture(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDescription {
+    Diffuse {
+        albedo: ColorDescription,
+        #[serde(default)]
+        roughness: f32,
+    },
+    Reflective {
+        albedo: [f32; 3],
+        #[serde(default)]
+        fuzz: f32,
+    },
+    Refractive {
+        index: f32,
+        absorption: Option<[f32; 3]>,
+        color_at_distance: Option<[f32; 3]>,
+        #[serde(default = "default_distance")]
+        distance: f32,
+    },
+    /// Glass that is frosted by its roughness and tinted where light passes through
+    Dielectric {
+        index: f32,
+        #[serde(default)]
+        roughness: f32,
+        tint: Option<ColorDescription>,
+        absorption: Option<[f32; 3]>,
+        color_at_distance: Option<[f32; 3]>,
+        #[serde(default = "default_distance")]
+        distance: f32,
+    },
+    /// A rough metal given by name or by its complex index of refraction eta + ik
+    Conductor {
+        metal: Option<MetalDescription>,
+        eta: Option<[f32; 3]>,
+        k: Option<[f32; 3]>,
+        #[serde(default)]
+        roughness: f32,
+        #[serde(default)]
+        anisotropic: f32,
+    },
+    /// The Disney material, whose parameters default to those of Principled::new
+    Principled {
+        base_color: ColorDescription,
+        metallic: Option<ValueDescription>,
+        roughness: Option<ValueDescription>,
+        specular: Option<ValueDescription>,
+        specular_tint: Option<ValueDescription>,
+        anisotropic: Option<ValueDescription>,
+        sheen: Option<ValueDescription>,
+        clearcoat: Option<ValueDescription>,
+        transmission: Option<ValueDescription>,
+        emission: Option<ColorDescription>,
+    },
+    /// A clear coat of the given thickness over the base material, which is described inline
+    Coated {
+        base: Box<MaterialDescription>,
+        #[serde(default = "default_coat_index")]
+        index: f32,
+        #[serde(default)]
+        roughness: f32,
+        #[serde(default = "default_distance")]
+        thickness: f32,
+        absorption: Option<[f32; 3]>,
+        color_at_distance: Option<[f32; 3]>,
+        #[serde(default = "default_distance")]
+        distance: f32,
+    },
+    Light {
+        emit: ColorDescription,
+        #[serde(default)]
+        two_sided: bool,
+    },
+    Isotropic {
+        albedo: ColorDescription,
+    },
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum MetalDescription {
+    Gold,
+    Copper,
+    Aluminium,
+    Silver,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum AxisDescription {
+    XY,
+    YZ,
+    XZ,
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum NormalsDescription {
+    #[default]
+    Smooth,
+    Flat,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ShapeDescription {
+    Sphere {
+        center: [f32; 3],
+        end_center: Option<[f32; 3]>,
+        radius: f32,
+        #[serde(default)]
+        start_time: f32,
+        #[serde(default = "default_end_time")]
+        end_time: f32,
+    },
+    Plane {
+        axis: AxisDescription,
+        r: [f32; 2],
+        s: [f32; 2],
+        k: f32,
+    },
+    Quad {
+        corner: [f32; 3],
+        u: [f32; 3],
+        v: [f32; 3],
+    },
+    Rectangle {
+        min: [f32; 3],
+        max: [f32; 3],
+    },
+    Mesh {
+        path: String,
+        #[serde(default)]
+        normals: NormalsDescription,
+    },
+    Volume {
+        density: f32,
+        albedo: ColorDescription,
+        boundary: Box<ObjectDescription>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum TransformDescription {
+    Translate([f32; 3]),
+    /// A rotation in degrees about the y axis
+    Rotate(f32),
+    RotateAxis { axis: [f32; 3], angle: f32 },
+    Scale(ScaleDescription),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScaleDescription {
+    Uniform(f32),
+    Axes([f32; 3]),
+}
+
+impl ScaleDescription {
+    fn factors(&self) -> Vec3 {
+        match self {
+            ScaleDescription::Uniform(factor) => Vec3::splat(*factor),
+            ScaleDescription::Axes(factors) => vector(*factors),
+        }
+    }
+}
+
+/// The translation, rotation in degrees about an axis and scale of an object at a time
+#[derive(Deserialize)]
+struct KeyframeDescription {
+    time: f32,
+    #[serde(default)]
+    translate: [f32; 3],
+    #[serde(default = "default_view")]
+    axis: [f32; 3],
+    #[serde(default)]
+    angle: f32,
+    #[serde(default = "default_scale")]
+    scale: ScaleDescription,
+}
+
+#[derive(Deserialize)]
+struct ObjectDescription {
+    #[serde(flatten)]
+    shape: ShapeDescription,
+    material: Option<String>,
+    #[serde(default)]
+    flip_normals: bool,
+    #[serde(default)]
+    transforms: Vec<TransformDescription>,
+    #[serde(default)]
+    animation: Vec<KeyframeDescription>,
+}
+
+fn default_light_selection() -> SelectionDescription {
+    SelectionDescription::Power
+}
+
+fn default_view() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_distance() -> f32 {
+    1.0
+}
+
+fn default_coat_index() -> f32 {
+    1.5
+}
+
+fn default_scale() -> ScaleDescription {
+    ScaleDescription::Uniform(1.0)
+}
+
+fn default_focus_distance() -> f32 {
+    10.0
+}
+
+fn default_end_time() -> f32 {
+    1.0
+}
+
+fn vector(v: [f32; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+/// Find the absorption of a medium given either as coefficients or as its color at a distance
+fn medium_absorption(absorption: &Option<[f32; 3]>,
+                     color_at_distance: &Option<[f32; 3]>,
+                     distance: f32)
+                     -> Result<Vec3, SceneError> {
+    match (absorption, color_at_distance) {
+        (Some(absorption), None) => Ok(vector(*absorption)),
+        (None, Some(color)) if distance > 0.0 => Ok(absorption_at_distance(vector(*color), distance)),
+        (None, Some(_)) => Err(SceneError::Invalid(String::from("the distance of a color must be positive"))),
+        (None, None) => Ok(Vec3::zero()),
+        (Some(_), Some(_)) => {
+            Err(SceneError::Invalid(String::from("give either the absorption or the color_at_distance \
+                                                  of a medium")))
+        }
+    }
+}
+
+/// Load a scene from a TOML scene file
+///
+/// The scene file describes the camera, named textures and materials, and a list
+/// of objects that reference the materials by name. Relative texture and mesh paths
+/// are resolved against the directory of the scene file. See scenes/cornell_box.toml
+/// for an example of the format. Objects that use the same mesh file and material
+/// share the BVH of the mesh and differ only by their transforms.
+///
+/// Spheres, planes and quads with a light material that are not transformed are
+/// collected as the light sources of the scene and are importance sampled. The light_selection
+/// key chooses whether lights are picked uniformly or by their power.
+pub fn load(filepath: &str,
+            width: usize,
+            height: usize)
+            -> Result<Scene, SceneError> {
+    let contents = fs::read_to_string(filepath)?;
+    let description: SceneDescription = toml::from_str(&contents)?;
+    let directory = Path::new(filepath).parent()
+                                       .map(Path::to_path_buf)
+                                       .unwrap_or_default();
+
+    let selection = match description.light_selection {
+        SelectionDescription::Uniform => LightSelection::Uniform,
+        SelectionDescription::Power => LightSelection::Power,
+    };
+
+    let mut builder = SceneBuilder { directory,
+                                     textures: HashMap::new(),
+                                     materials: HashMap::new(),
+                                     meshes: HashMap::new(),
+                                     lights: LightList::new(selection) };
+
+    for (name, texture) in &description.textures {
+        let texture = builder.build_texture(texture)?;
+        builder.textures.insert(name.clone(), texture);
+    }
+
+    for (name, material) in &description.materials {
+        let emission = builder.emission(material)?;
+        let material = builder.build_material(material)?;
+        builder.materials.insert(name.clone(), (material, emission));
+    }
+
+    let mut objects: Vec<Arc<dyn Hitable>> = Vec::new();
+    for object in &description.objects {
+        objects.push(builder.build_object(object, false)?);
+    }
+
+    if objects.is_empty() {
+        return Err(SceneError::Invalid(String::from("the scene has no objects")));
+    }
+
+    let camera = &description.camera;
+    let camera = Camera::new(vector(camera.origin),
+                             vector(camera.lookat),
+                             vector(camera.view),
+                             camera.fov,
+                             width as f32 / height as f32,
+                             camera.aperture,
+                             camera.focus_distance,
+                             camera.start_time,
+                             camera.end_time,
+                             camera.atmosphere);
+
+    let bvh = BVH::new(&mut objects, camera.start_time, camera.end_time);
+
+    let name = description.name.unwrap_or_else(|| filepath.to_string());
+
+    Ok(Scene::new(&name, camera, bvh, builder.lights))
+}
+
+struct SceneBuilder {
+    directory: PathBuf,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, (Arc<dyn Material>, Option<Vec3>)>,
+    // meshes by path, material and normals so that every use of a mesh shares its BVH
+    meshes: HashMap<(String, Option<String>, Normals), TriangleMesh>,
+    lights: LightList,
+}
+
+impl SceneBuilder {
+    fn resolve_path(&self, path: &str) -> String {
+        self.directory.join(path).to_string_lossy().into_owned()
+    }
+
+    fn build_texture(&self, texture: &TextureDescription) -> Result<Arc<dyn Texture>, SceneError> {
+        match texture {
+            TextureDescription::Constant { color } => {
+                Ok(Arc::new(ConstantTexture::new(color[0], color[1], color[2])))
+            }
+            TextureDescription::Image { path } => {
+                let path = self.resolve_path(path);
+                if !Path::new(&path).is_file() {
+                    return Err(SceneError::Invalid(format!("image '{}' does not exist", path)));
+                }
+                Ok(Arc::new(ImageTexture::new(&path)))
+            }
+        }
+    }
+
+    fn color(&self, color: &ColorDescription) -> Result<Arc<dyn Texture>, SceneError> {
+        match color {
+            ColorDescription::Rgb(rgb) => Ok(Arc::new(ConstantTexture::new(rgb[0], rgb[1], rgb[2]))),
+            ColorDescription::Texture(name) => {
+                self.textures
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| SceneError::Invalid(format!("unknown texture '{}'", name)))
+            }
+        }
+    }
+
+    /// Find the texture of a value, or use the default if there is none
+    fn value(&self,
+             value: &Option<ValueDescription>,
+             default: &Arc<dyn Texture>)
+             -> Result<Arc<dyn Texture>, SceneError> {
+        match value {
+            Some(ValueDescription::Value(value)) => Ok(Arc::new(ConstantTexture::new(*value, *value, *value))),
+            Some(ValueDescription::Texture(name)) => self.color(&ColorDescription::Texture(name.clone())),
+            None => Ok(default.clone()),
+        }
+    }
+
+    /// Find the color emitted by a light or a glowing principled material
+    ///
+    /// Textured lights use the color at the center of the texture.
+    /// Principled materials that emit nothing there are not lights.
+    fn emission(&self, material: &MaterialDescription) -> Result<Option<Vec3>, SceneError> {
+        match material {
+            MaterialDescription::Light { emit, .. } => {
+                Ok(Some(self.color(emit)?.value(0.5, 0.5, &Vec3::zero())))
+            }
+            MaterialDescription::Principled { emission: Some(emission), .. } => {
+                let emission = self.color(emission)?.value(0.5, 0.5, &Vec3::zero());
+                Ok(if emission.max_element() > 0.0 { Some(emission) } else { None })
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn build_material(&self,
+                      material: &MaterialDescription)
+                      -> Result<Arc<dyn Material>, SceneError> {
+        let material: Arc<dyn Material> = match material {
+            MaterialDescription::Diffuse { albedo, roughness } => {
+                Arc::new(Diffuse::from_box(self.color(albedo)?, *roughness))
+            }
+            MaterialDescription::Reflective { albedo, fuzz } => {
+                Arc::new(Reflective::new(vector(*albedo), *fuzz))
+            }
+            MaterialDescription::Refractive { index,
+                                              absorption,
+                                              color_at_distance,
+                                              distance, } => {
+                let absorption = medium_absorption(absorption, color_at_distance, *distance)?;
+                Arc::new(Refractive::new(*index).with_absorption(absorption))
+            }
+            MaterialDescription::Dielectric { index,
+                                              roughness,
+                                              tint,
+                                              absorption,
+                                              color_at_distance,
+                                              distance, } => {
+                let tint = match tint {
+                    Some(tint) => self.color(tint)?,
+                    None => Arc::new(ConstantTexture::new(1.0, 1.0, 1.0)),
+                };
+                let absorption = medium_absorption(absorption, color_at_distance, *distance)?;
+                Arc::new(RoughDielectric::from_box(*index, *roughness, tint).with_absorption(absorption))
+            }
+            MaterialDescription::Conductor { metal,
+                                             eta,
+                                             k,
+                                             roughness,
+                                             anisotropic, } => {
+                let conductor = match (metal, eta, k) {
+                    (Some(MetalDescription::Gold), None, None) => Conductor::gold(*roughness),
+                    (Some(MetalDescription::Copper), None, None) => Conductor::copper(*roughness),
+                    (Some(MetalDescription::Aluminium), None, None) => Conductor::aluminium(*roughness),
+                    (Some(MetalDescription::Silver), None, None) => Conductor::silver(*roughness),
+                    (None, Some(eta), Some(k)) => Conductor::new(vector(*eta), vector(*k), *roughness),
+                    _ => {
+                        return Err(SceneError::Invalid(String::from("a conductor requires either a \
+                                                                     metal or both eta and k")))
+                    }
+                };
+                Arc::new(conductor.with_anisotropy(*anisotropic))
+            }
+            MaterialDescription::Principled { base_color,
+                                              metallic,
+                                              roughness,
+                                              specular,
+                                              specular_tint,
+                                              anisotropic,
+                                              sheen,
+                                              clearcoat,
+                                              transmission,
+                                              emission, } => {
+                let defaults = Principled::from_box(self.color(base_color)?);
+                let emission = match emission {
+                    Some(emission) => self.color(emission)?,
+                    None => defaults.emission.clone(),
+                };
+                Arc::new(Principled { metallic: self.value(metallic, &defaults.metallic)?,
+                                      roughness: self.value(roughness, &defaults.roughness)?,
+                                      specular: self.value(specular, &defaults.specular)?,
+                                      specular_tint: self.value(specular_tint, &defaults.specular_tint)?,
+                                      anisotropic: self.value(anisotropic, &defaults.anisotropic)?,
+                                      sheen: self.value(sheen, &defaults.sheen)?,
+                                      clearcoat: self.value(clearcoat, &defaults.clearcoat)?,
+                                      transmission: self.value(transmission, &defaults.transmission)?,
+                                      emission,
+                                      ..defaults })
+            }
+            MaterialDescription::Coated { base,
+                                          index,
+                                          roughness,
+                                          thickness,
+                                          absorption,
+                                          color_at_distance,
+                                          distance, } => {
+                // volumes scatter light inside them rather than off a surface a coat could cover
+                if let MaterialDescription::Isotropic { .. } = **base {
+                    return Err(SceneError::Invalid(String::from("a coat cannot be laid over an isotropic \
+                                                                 material")));
+                }
+                let absorption = medium_absorption(absorption, color_at_distance, *distance)?;
+                let coated = Coated::from_box(self.build_material(base)?, *index, *roughness);
+                Arc::new(coated.with_absorption(absorption, *thickness))
+            }
+            MaterialDescription::Light { emit, two_sided } => {
+                Arc::new(Light { emit: self.color(emit)?,
+                                 two_sided: *two_sided })
+            }
+            MaterialDescription::Isotropic { albedo } => {
+                Arc::new(Isotropic::from_box(self.color(albedo)?))
+            }
+        };
+        Ok(material)
+    }
+
+    /// Find the named material and the color it emits if it is a light
+    ///
+    /// Only the boundaries of volumes may omit their material since
+    /// they are never shaded.
+    fn material(&self,
+                name: &Option<String>,
+                boundary: bool)
+                -> Result<(Arc<dyn Material>, Option<Vec3>), SceneError> {
+        match name {
+            Some(name) => {
+                self.materials
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| SceneError::Invalid(format!("unknown material '{}'", name)))
+            }
+            None if boundary => Ok((Arc::new(Empty::new()), None)),
+            None => Err(SceneError::Invalid(String::from("every object requires a material"))),
+        }
+    }
+
+    fn build_object(&mut self,
+                    object: &ObjectDescription,
+                    boundary: bool)
+                    -> Result<Arc<dyn Hitable>, SceneError> {
+        // the emission and area of shapes that can be sampled as lights
+        let mut emitter = None;
+
+        let mut hitable: Arc<dyn Hitable> = match &object.shape {
+            ShapeDescription::Sphere { center,
+                                       end_center,
+                                       radius,
+                                       start_time,
+                                       end_time, } => {
+                let (material, emission) = self.material(&object.material, boundary)?;
+                let sphere = Sphere::from_box(vector(*center),
+                                              vector(end_center.unwrap_or(*center)),
+                                              *radius,
+                                              material,
+                                              *start_time,
+                                              *end_time);
+                emitter = emission.map(|emission| (emission, sphere.area()));
+                Arc::new(sphere)
+            }
+            ShapeDescription::Plane { axis, r, s, k } => {
+                let (material, emission) = self.material(&object.material, boundary)?;
+                let axis = match axis {
+                    AxisDescription::XY => Axis::XY,
+                    AxisDescription::YZ => Axis::YZ,
+                    AxisDescription::XZ => Axis::XZ,
+                };
+                let plane = Plane::from_box(axis, r[0], r[1], s[0], s[1], *k, material);
+                emitter = emission.map(|emission| (emission, plane.area()));
+                Arc::new(plane)
+            }
+            ShapeDescription::Quad { corner, u, v } => {
+                let (material, emission) = self.material(&object.material, boundary)?;
+                let quad = Quad::from_box(vector(*corner), vector(*u), vector(*v), material);
+                emitter = emission.map(|emission| (emission, quad.area()));
+                Arc::new(quad)
+            }
+            ShapeDescription::Rectangle { min, max } => {
+                let (material, _) = self.material(&object.material, boundary)?;
+                Arc::new(Rectangle::new(vector(*min), vector(*max), material))
+            }
+            ShapeDescription::Mesh { path, normals } => {
+                // faces without a material of their own in the MTL file are grey
+                let material = match object.material {
+                    Some(_) => self.material(&object.material, boundary)?.0,
+                    None => Arc::new(Diffuse::new(ConstantTexture::new(0.5, 0.5, 0.5), 0.0)),
+                };
+                let normals = match normals {
+                    NormalsDescription::Smooth => Normals::Smooth,
+                    NormalsDescription::Flat => Normals::Flat,
+                };
+                let path = self.resolve_path(path);
+                let key = (path.clone(), object.material.clone(), normals);
+                let mesh = match self.meshes.get(&key) {
+                    Some(mesh) => mesh.clone(),
+                    None => {
+                        let mesh = if path.to_lowercase().ends_with(".ply") {
+                            ply::load(&path, material, normals)
+                        } else {
+                            obj::load(&path, material, normals)
+                        };
+                        let mesh = mesh.map_err(|error| SceneError::Invalid(error.to_string()))?;
+                        self.meshes.insert(key, mesh.clone());
+                        mesh
+                    }
+                };
+                Arc::new(mesh)
+            }
+            ShapeDescription::Volume { density,
+                                       albedo,
+                                       boundary, } => {
+                let boundary = self.build_object(boundary, true)?;
+                Arc::new(Volume::from_box(*density, boundary, self.color(albedo)?))
+            }
+        };
+
+        if object.flip_normals {
+            hitable = Arc::new(FlipNormals::from_box(hitable));
+        }
+
+        if let Some((emission, area)) = emitter {
+            if !boundary && object.transforms.is_empty() && object.animation.is_empty() {
+                self.lights.add_box(hitable.clone(), luminance(&emission) * area);
+            }
+        }
+
+        // the transforms are applied in order, so later ones multiply from the left
+        if !object.transforms.is_empty() {
+            let matrix = object.transforms.iter().fold(Mat4::identity(), |matrix, transform| {
+                let next = match transform {
+                    TransformDescription::Translate(offset) => translation(vector(*offset)),
+                    TransformDescription::Rotate(angle) => rotation(Vec3::new(0.0, 1.0, 0.0), *angle),
+                    TransformDescription::RotateAxis { axis, angle } => rotation(vector(*axis), *angle),
+                    TransformDescription::Scale(scale) => scaling(scale.factors()),
+                };
+                next * matrix
+            });
+            hitable = Arc::new(Transform::from_box(matrix, hitable));
+        }
+
+        // the animation moves the object after its static transforms
+        if !object.animation.is_empty() {
+            let keyframes = object.animation
+                                  .iter()
+                                  .map(|keyframe| {
+                                      let axis = vector(keyframe.axis).normalize();
+                                      let rotation = Quat::from_axis_angle(axis, keyframe.angle.to_radians());
+                                      Keyframe::new(keyframe.time,
+                                                    vector(keyframe.translate),
+                                                    rotation,
+                                                    keyframe.scale.factors())
+                                  })
+                                  .collect();
+            hitable = Arc::new(AnimatedTransform::from_box(keyframes, hitable));
+        }
+
+        Ok(hitable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_scene_file() {
+        let scene = load("scenes/cornell_box.toml", 64, 64).unwrap();
+
+        assert_eq!(scene.name, "Cornell Box");
+        assert!(scene.world.bounding_box(0.0, 1.0).is_some());
+        assert_eq!(scene.lights.len(), 1);
+        assert!(scene.lights.bounding_box(0.0, 1.0).unwrap().minimum.y() > 553.0);
+    }
+
+    #[test]
+    fn test_principled_emission_is_sampled() {
+        let description = "[camera]
+                           origin = [0.0, 0.0, 0.0]
+                           lookat = [0.0, 0.0, -1.0]
+                           fov = 40.0
+
+                           [materials.glowing]
+                           type = \"principled\"
+                           base_color = [0.5, 0.5, 0.5]
+                           emission = [4.0, 4.0, 4.0]
+
+                           [materials.dark]
+                           type = \"principled\"
+                           base_color = [0.5, 0.5, 0.5]
+                           emission = [0.0, 0.0, 0.0]
+
+                           [[objects]]
+                           type = \"sphere\"
+                           center = [0.0, 0.0, -1.0]
+                           radius = 0.5
+                           material = \"glowing\"
+
+                           [[objects]]
+                           type = \"sphere\"
+                           center = [0.0, 0.0, -3.0]
+                           radius = 0.5
+                           material = \"dark\"";
+        let description: SceneDescription = toml::from_str(description).unwrap();
+        let mut builder = SceneBuilder { directory: PathBuf::new(),
+                                         textures: HashMap::new(),
+                                         materials: HashMap::new(),
+                                         meshes: HashMap::new(),
+                                         lights: LightList::new(LightSelection::Power) };
+        for (name, material) in &description.materials {
+            let emission = builder.emission(material).unwrap();
+            let material = builder.build_material(material).unwrap();
+            builder.materials.insert(name.clone(), (material, emission));
+        }
+        for object in &description.objects {
+            builder.build_object(object, false).unwrap();
+        }
+
+        // only the sphere that glows is sampled as a light
+        assert_eq!(builder.lights.len(), 1);
+        assert!(builder.lights.bounding_box(0.0, 1.0).unwrap().minimum.z() > -1.6);
+    }
+
+    #[test]
+    fn test_invalid_scene_file() {
+        let description = "[camera]
+                           origin = [0.0, 0.0, 0.0]
+                           lookat = [0.0, 0.0, -1.0]
+                           fov = 40.0
+
+                           [[objects]]
+                           type = \"sphere\"
+                           center = [0.0, 0.0, -1.0]
+                           radius = 0.5
+                           material = \"missing\"";
+        let description: SceneDescription = toml::from_str(description).unwrap();
+        let mut builder = SceneBuilder { directory: PathBuf::new(),
+                                         textures: HashMap::new(),
+                                         materials: HashMap::new(),
+                                         meshes: HashMap::new(),
+                                         lights: LightList::new(LightSelection::Power) };
+
+        assert!(builder.build_object(&description.objects[0], false).is_err());
+
+        // coats may be laid over coats but not over volumes
+        let coated = |base: &str| -> MaterialDescription {
+            toml::from_str(&format!("type = \"coated\"\nroughness = 0.3\nbase = {}", base)).unwrap()
+        };
+        let nested = coated("{ type = \"coated\", base = { type = \"diffuse\", albedo = [0.5, 0.5, 0.5] } }");
+        assert!(builder.build_material(&nested).is_ok());
+        let volume = coated("{ type = \"isotropic\", albedo = [0.5, 0.5, 0.5] }");
+        assert!(builder.build_material(&volume).is_err());
+    }
+}
@@ -1,12 +1,16 @@
+use std::f32;
 use std::f32::consts::PI;
 use std::sync::Arc;
 
 use glam::Vec3;
 
 use aabb::AABB;
+use basis::OrthonormalBasis;
 use hitable::{HitRecord, Hitable};
 use materials::Material;
 use ray::Ray;
+use sampler::Sampler;
+use sampling;
 
 #[derive(Clone)]
 pub struct Sphere {
@@ -40,11 +44,45 @@ impl Sphere {
                  end_time }
     }
 
+    pub fn from_box(start_center: Vec3,
+                    end_center: Vec3,
+                    radius: f32,
+                    material: Arc<dyn Material>,
+                    start_time: f32,
+                    end_time: f32)
+                    -> Sphere {
+        Sphere { start_center,
+                 end_center,
+                 radius,
+                 material,
+                 start_time,
+                 end_time }
+    }
+
     pub fn center(&self, time: f32) -> Vec3 {
         self.start_center
         + ((time - self.start_time) / (self.end_time - self.start_time))
           * (self.end_center - self.start_center)
     }
+
+    pub fn area(&self) -> f32 {
+        4.0 * PI * self.radius * self.radius
+    }
+
+    /// The cosine of the half angle of the cone the sphere subtends from origin
+    ///
+    /// None is returned when the origin lies inside of the sphere
+    /// as the sphere then covers every direction.
+    fn cos_theta_max(&self, origin: Vec3, time: f32) -> Option<f32> {
+        let distance_squared = (self.center(time) - origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+
+        if distance_squared <= radius_squared {
+            None
+        } else {
+            Some((1.0 - radius_squared / distance_squared).max(0.0).sqrt())
+        }
+    }
 }
 
 fn get_sphere_uv(p: &Vec3) -> (f32, f32) {
@@ -55,6 +93,16 @@ fn get_sphere_uv(p: &Vec3) -> (f32, f32) {
     (u, v)
 }
 
+/// The direction in which u increases around the sphere, which has none at the poles
+fn sphere_tangent(p: &Vec3) -> Option<Vec3> {
+    let tangent = Vec3::new(p.z(), 0.0, -p.x());
+    if tangent.length() > 1e-6 {
+        Some(tangent.normalize())
+    } else {
+        None
+    }
+}
+
 impl Hitable for Sphere {
     /// Determine if the given ray intersects with a point on the sphere
     ///
@@ -80,13 +128,15 @@ impl Hitable for Sphere {
                     let point = ray.point_at_parameter(root);
                     let normal = (point - self.center(ray.time)) / self.radius;
                     let (u, v) = get_sphere_uv(&normal);
-                    return Some(HitRecord::new(root,
-                                               u,
-                                               v,
-                                               point,
-                                               normal,
-                                               normal,
-                                               self.material.clone()));
+                    let mut record = HitRecord::new(root,
+                                                    u,
+                                                    v,
+                                                    point,
+                                                    normal,
+                                                    normal,
+                                                    self.material.clone());
+                    record.tangent = sphere_tangent(&normal);
+                    return Some(record);
                 }
             }
         }
@@ -94,7 +144,8 @@ impl Hitable for Sphere {
     }
 
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
-        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        // hollow spheres have a negative radius but the same extent
+        let radius = Vec3::splat(self.radius.abs());
         let min0 = self.center(t0) - radius;
         let max0 = self.center(t0) + radius;
         let min1 = self.center(t1) - radius;
@@ -105,4 +156,82 @@ impl Hitable for Sphere {
 
         Some(small.surrounding_box(&big))
     }
+
+    /// Compute the solid angle density of sampling direction from origin
+    ///
+    /// Directions are sampled uniformly within the cone subtended by the
+    /// sphere, so the density is one over the solid angle of that cone.
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+        if self.hit(&Ray::new(origin, direction, time), 0.001, f32::MAX).is_none() {
+            return 0.0;
+        }
+
+        match self.cos_theta_max(origin, time) {
+            Some(cos_theta_max) => 1.0 / (2.0 * PI * (1.0 - cos_theta_max)),
+            None => 1.0 / (4.0 * PI),
+        }
+    }
+
+    /// Sample a direction from origin within the cone subtended by the sphere
+    ///
+    /// The sphere is sampled where it is at the given time so that
+    /// moving spheres are sampled along their motion.
+    fn pdf_random(&self, origin: Vec3, time: f32, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        let (u, v) = sampler.get_2d();
+
+        match self.cos_theta_max(origin, time) {
+            Some(cos_theta_max) => {
+                let basis = OrthonormalBasis::new(&(self.center(time) - origin));
+                Some(basis.local(&sampling::uniform_sample_cone(u, v, cos_theta_max)))
+            }
+            None => Some(sampling::uniform_sample_sphere(u, v)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use materials::Empty;
+    use sampler::IndependentSampler;
+
+    #[test]
+    fn test_sphere_pdf() {
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, 0.0),
+                                 Vec3::new(4.0, 0.0, 0.0),
+                                 1.0,
+                                 Empty::new(),
+                                 0.0,
+                                 1.0);
+        let origin = Vec3::new(2.0, 5.0, 0.0);
+        let mut sampler = IndependentSampler::new(100, 0);
+
+        // the sphere is at (2, 0, 0) halfway through its motion
+        for _ in 0..100 {
+            let direction = sphere.pdf_random(origin, 0.5, &mut sampler).unwrap();
+            assert!(sphere.hit(&Ray::new(origin, direction, 0.5), 0.001, f32::MAX)
+                          .is_some());
+        }
+
+        let expected = 1.0 / (2.0 * PI * (1.0 - (1.0f32 - 1.0 / 25.0).sqrt()));
+        let pdf = sphere.pdf_value(origin, Vec3::new(0.0, -1.0, 0.0), 0.5);
+        assert!((pdf - expected).abs() < 1e-3 * expected);
+        assert_eq!(sphere.pdf_value(origin, Vec3::new(0.0, -1.0, 0.0), 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_sphere_tangent() {
+        let sphere = Sphere::new(Vec3::zero(), Vec3::zero(), 1.0, Empty::new(), 0.0, 1.0);
+
+        // the tangent points where u increases and is perpendicular to the normal
+        let ray = Ray::new(Vec3::new(3.0, 0.5, 1.0), Vec3::new(-1.0, -0.2, -0.3), 0.0);
+        let hit = sphere.hit(&ray, 0.001, f32::MAX).unwrap();
+        let tangent = hit.tangent.unwrap();
+        assert!(tangent.dot(hit.shading_normal).abs() < 1e-6);
+        let (u, _) = get_sphere_uv(&(hit.point + 1e-3 * tangent).normalize());
+        assert!(u > hit.u);
+
+        let pole = Ray::new(Vec3::new(0.0, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        assert!(sphere.hit(&pole, 0.001, f32::MAX).unwrap().tangent.is_none());
+    }
 }
@@ -39,6 +39,12 @@ impl ImageTexture {
     pub fn new(filename: &str) -> ImageTexture {
         ImageTexture { im: image::open(filename).unwrap().flipv().to_rgb() }
     }
+
+    /// Create a texture from RGB pixels stored row by row from the top of the image
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<u8>) -> Option<ImageTexture> {
+        let im = image::RgbImage::from_raw(width, height, pixels)?;
+        Some(ImageTexture { im: image::imageops::flip_vertical(&im) })
+    }
 }
 
 /// Determine which pixel to retrieve from the image by
@@ -53,3 +59,32 @@ impl Texture for ImageTexture {
         Vec3::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0)
     }
 }
+
+/// FloatTexture keeps the colors of an image as floats, for images more precise than 8 bits
+pub struct FloatTexture {
+    width: u32,
+    height: u32,
+    pixels: Vec<Vec3>,
+}
+
+impl FloatTexture {
+    /// Create a texture from colors stored row by row from the top of the image
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<Vec3>) -> Option<FloatTexture> {
+        if width == 0 || height == 0 || pixels.len() < width as usize * height as usize {
+            return None;
+        }
+        Some(FloatTexture { width,
+                            height,
+                            pixels })
+    }
+}
+
+/// Find the pixel the same way as ImageTexture, with v = 0 at the bottom of the image
+impl Texture for FloatTexture {
+    fn value(&self, u: f32, v: f32, _p: &Vec3) -> Vec3 {
+        let i = 0.0f32.max((u * self.width as f32).min(self.width as f32 - 1.0)) as u32;
+        let j = 0.0f32.max((v * self.height as f32).min(self.height as f32 - 1.0)) as u32;
+
+        self.pixels[((self.height - 1 - j) * self.width + i) as usize]
+    }
+}
@@ -2,7 +2,7 @@ use std::f32;
 use std::f32::consts::PI;
 use std::sync::Arc;
 
-use glam::Vec3;
+use glam::{Mat4, Quat, Vec3, Vec4};
 
 use aabb::AABB;
 use hitable::{HitRecord, Hitable};
@@ -18,11 +18,16 @@ impl Translate {
         let hitable = Arc::new(hitable);
         Translate { offset, hitable }
     }
+
+    pub fn from_box(offset: Vec3, hitable: Arc<dyn Hitable>) -> Translate {
+        Translate { offset, hitable }
+    }
 }
 
 impl Hitable for Translate {
     fn hit(&self, ray: &Ray, position_min: f32, position_max: f32) -> Option<HitRecord> {
-        let moved_ray = Ray::new(ray.origin - self.offset, ray.direction, ray.time);
+        let mut moved_ray = Ray::new(ray.origin - self.offset, ray.direction, ray.time);
+        moved_ray.medium_sample = ray.medium_sample;
         if let Some(mut hit) = self.hitable.hit(&moved_ray, position_min, position_max) {
             hit.point += self.offset;
             Some(hit)
@@ -51,7 +56,10 @@ pub struct Rotate {
 
 impl Rotate {
     pub fn new<H: Hitable + 'static>(angle: f32, hitable: H) -> Rotate {
-        let hitable = Arc::new(hitable);
+        Rotate::from_box(angle, Arc::new(hitable))
+    }
+
+    pub fn from_box(angle: f32, hitable: Arc<dyn Hitable>) -> Rotate {
         let radians = (PI / 180.0) * angle;
         let sin_theta = radians.sin();
         let cos_theta = radians.cos();
@@ -78,11 +86,14 @@ impl Hitable for Rotate {
         let origin = self.rotate(&ray.origin);
         let direction = self.rotate(&ray.direction);
 
-        let rotated_ray = Ray::new(origin, direction, ray.time);
+        let mut rotated_ray = Ray::new(origin, direction, ray.time);
+        rotated_ray.medium_sample = ray.medium_sample;
 
         if let Some(mut hit) = self.hitable.hit(&rotated_ray, t0, t1) {
             hit.point = self.rotate_inv(&hit.point);
+            hit.geometric_normal = self.rotate_inv(&hit.geometric_normal);
             hit.shading_normal = self.rotate_inv(&hit.shading_normal);
+            hit.tangent = hit.tangent.map(|tangent| self.rotate_inv(&tangent));
             Some(hit)
         } else {
             None
@@ -130,19 +141,31 @@ impl Scale {
         let hitable = Arc::new(hitable);
         Scale { scalar, hitable }
     }
+
+    pub fn from_box(scalar: f32, hitable: Arc<dyn Hitable>) -> Scale {
+        Scale { scalar, hitable }
+    }
 }
 
 impl Hitable for Scale {
     /// Reference: http://woo4.me/raytracer/translations/
+    ///
+    /// The scaled ray travels 1 / |scalar| as far for the same parameter,
+    /// so the interval and the parameter of the hit are scaled to match.
     fn hit(&self, ray: &Ray, t0: f32, t1: f32) -> Option<HitRecord> {
         let origin = ray.origin / self.scalar;
-        let direction = (ray.direction / self.scalar).normalize();
+        let direction = ray.direction / self.scalar;
+        let length = self.scalar.abs();
 
-        let scaled_ray = Ray::new(origin, direction, ray.time);
+        let mut scaled_ray = Ray::new(origin, direction, ray.time);
+        scaled_ray.medium_sample = ray.medium_sample;
 
-        if let Some(mut hit) = self.hitable.hit(&scaled_ray, t0, t1) {
-            hit.point = hit.point * self.scalar;
+        if let Some(mut hit) = self.hitable.hit(&scaled_ray, t0 / length, t1 / length) {
+            hit.parameter *= length;
+            hit.point *= self.scalar;
+            hit.geometric_normal = (hit.geometric_normal / self.scalar).normalize();
             hit.shading_normal = (hit.shading_normal / self.scalar).normalize();
+            hit.tangent = hit.tangent.map(|tangent| (tangent * self.scalar).normalize());
             Some(hit)
         } else {
             None
@@ -151,11 +174,376 @@ impl Hitable for Scale {
 
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
         if let Some(mut bbox) = self.hitable.bounding_box(t0, t1) {
-            bbox.minimum *= self.scalar;
-            bbox.maximum *= self.scalar;
+            let (minimum, maximum) = (bbox.minimum * self.scalar, bbox.maximum * self.scalar);
+            bbox.minimum = minimum.min(maximum);
+            bbox.maximum = minimum.max(maximum);
             Some(bbox)
         } else {
             None
         }
     }
 }
+
+/// The matrix of a translation by the offset
+pub fn translation(offset: Vec3) -> Mat4 {
+    Mat4::from_translation(offset)
+}
+
+/// The matrix of a counterclockwise rotation in degrees about the axis
+pub fn rotation(axis: Vec3, angle: f32) -> Mat4 {
+    Mat4::from_axis_angle(axis.normalize(), angle.to_radians())
+}
+
+/// The matrix of a scaling by a factor along each axis
+pub fn scaling(factors: Vec3) -> Mat4 {
+    Mat4::from_scale(factors)
+}
+
+/// Transform places a hitable with an affine 4x4 matrix
+///
+/// The matrix is composed from translations, rotations and scalings, e.g.
+/// translation(offset) * rotation(axis, angle) * scaling(factors), where the
+/// rightmost is applied first. Rays are moved into the space of the hitable
+/// and its hits are moved back, with normals transformed by the inverse
+/// transpose so that they stay perpendicular to non-uniformly scaled surfaces.
+/// Many transforms can share one hitable to place instances of it.
+#[derive(Clone)]
+pub struct Transform {
+    matrix: Mat4,
+    inverse: Mat4,
+    normal_matrix: Mat4,
+    hitable: Arc<dyn Hitable>,
+}
+
+impl Transform {
+    pub fn new<H: Hitable + 'static>(matrix: Mat4, hitable: H) -> Transform {
+        Transform::from_box(matrix, Arc::new(hitable))
+    }
+
+    pub fn from_box(matrix: Mat4, hitable: Arc<dyn Hitable>) -> Transform {
+        let inverse = matrix.inverse();
+        Transform { matrix,
+                    inverse,
+                    normal_matrix: inverse.transpose(),
+                    hitable }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        self.matrix
+    }
+}
+
+impl Hitable for Transform {
+    /// Intersect the ray moved into the space of the hitable
+    ///
+    /// The moved direction is normalized by the ray, so parameters in the
+    /// space of the hitable are scaled by the length of the moved direction.
+    fn hit(&self, ray: &Ray, position_min: f32, position_max: f32) -> Option<HitRecord> {
+        hit_transformed(&*self.hitable,
+                        &self.matrix,
+                        &self.inverse,
+                        &self.normal_matrix,
+                        ray,
+                        position_min,
+                        position_max)
+    }
+
+    /// Bound the transformed corners of the bounding box of the hitable
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        let bbox = self.hitable.bounding_box(t0, t1)?;
+        Some(transform_box(&self.matrix, &bbox))
+    }
+}
+
+/// Intersect a hitable placed by a matrix, given with its inverse and inverse transpose
+///
+/// The moved direction is normalized by the ray, so parameters in the
+/// space of the hitable are scaled by the length of the moved direction.
+fn hit_transformed(hitable: &dyn Hitable,
+                   matrix: &Mat4,
+                   inverse: &Mat4,
+                   normal_matrix: &Mat4,
+                   ray: &Ray,
+                   position_min: f32,
+                   position_max: f32)
+                   -> Option<HitRecord> {
+    let origin = inverse.transform_point3(ray.origin);
+    let direction = inverse.transform_vector3(ray.direction);
+    let length = direction.length();
+
+    let mut moved_ray = Ray::new(origin, direction, ray.time);
+    moved_ray.medium_sample = ray.medium_sample;
+
+    let mut hit = hitable.hit(&moved_ray, position_min * length, position_max * length)?;
+    hit.parameter /= length;
+    hit.point = matrix.transform_point3(hit.point);
+    hit.geometric_normal = normal_matrix.transform_vector3(hit.geometric_normal).normalize();
+    hit.shading_normal = normal_matrix.transform_vector3(hit.shading_normal).normalize();
+    hit.tangent = hit.tangent.map(|tangent| matrix.transform_vector3(tangent).normalize());
+    Some(hit)
+}
+
+/// The eight corners of a box
+fn corners(bbox: &AABB) -> [Vec3; 8] {
+    let mut corners = [Vec3::zero(); 8];
+    for (i, corner) in corners.iter_mut().enumerate() {
+        *corner = Vec3::new(if i & 1 == 0 { bbox.minimum.x() } else { bbox.maximum.x() },
+                            if i & 2 == 0 { bbox.minimum.y() } else { bbox.maximum.y() },
+                            if i & 4 == 0 { bbox.minimum.z() } else { bbox.maximum.z() });
+    }
+    corners
+}
+
+/// Find the bounding box of the corners of a box moved by the matrix
+fn transform_box(matrix: &Mat4, bbox: &AABB) -> AABB {
+    let mut minimum = Vec3::splat(f32::MAX);
+    let mut maximum = Vec3::splat(f32::MIN);
+
+    for corner in corners(bbox).iter() {
+        let point = matrix.transform_point3(*corner);
+        minimum = minimum.min(point);
+        maximum = maximum.max(point);
+    }
+
+    AABB::from(minimum, maximum)
+}
+
+/// The number of times between two keyframes at which an animated bounding box is sampled
+const BOUNDING_BOX_SAMPLES: usize = 16;
+
+/// Keyframe is the translation, rotation and scale of an animated object at a time
+///
+/// The object is scaled first, then rotated, then translated.
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+    pub time: f32,
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Keyframe {
+    pub fn new(time: f32, translation: Vec3, rotation: Quat, scale: Vec3) -> Keyframe {
+        Keyframe { time,
+                   translation,
+                   rotation,
+                   scale }
+    }
+
+    /// Interpolate between this keyframe and a later one at the given time
+    ///
+    /// Translation and scale are interpolated linearly and the rotation
+    /// along the shortest arc between the two orientations.
+    fn interpolate(&self, next: &Keyframe, time: f32) -> Keyframe {
+        let span = next.time - self.time;
+        let t = if span > 0.0 { ((time - self.time) / span).clamp(0.0, 1.0) } else { 0.0 };
+
+        Keyframe { time,
+                   translation: self.translation + t * (next.translation - self.translation),
+                   rotation: slerp(self.rotation, next.rotation, t),
+                   scale: self.scale + t * (next.scale - self.scale) }
+    }
+
+    fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+/// Spherically interpolate between two unit quaternions
+fn slerp(start: Quat, end: Quat, t: f32) -> Quat {
+    let start = Vec4::from(start);
+    let mut end = Vec4::from(end);
+
+    // q and -q are the same rotation, so turn the shorter way around
+    let mut cosine = start.dot(end);
+    if cosine < 0.0 {
+        end = -end;
+        cosine = -cosine;
+    }
+
+    // nearly parallel rotations are interpolated linearly to avoid dividing by zero
+    if cosine > 0.9995 {
+        return Quat::from(start + t * (end - start)).normalize();
+    }
+
+    let angle = cosine.acos();
+    let sine = angle.sin();
+    Quat::from(((1.0 - t) * angle).sin() / sine * start + (t * angle).sin() / sine * end)
+}
+
+/// AnimatedTransform moves a hitable through keyframes over the time of the rays
+///
+/// The transform at the time of a ray is interpolated between the two
+/// keyframes around it, and held at the first or last keyframe outside them,
+/// so that any object blurs with its motion.
+#[derive(Clone)]
+pub struct AnimatedTransform {
+    keyframes: Vec<Keyframe>,
+    hitable: Arc<dyn Hitable>,
+}
+
+impl AnimatedTransform {
+    pub fn new<H: Hitable + 'static>(keyframes: Vec<Keyframe>, hitable: H) -> AnimatedTransform {
+        AnimatedTransform::from_box(keyframes, Arc::new(hitable))
+    }
+
+    /// Create an animated transform from keyframes in any order
+    ///
+    /// Panics when there are no keyframes.
+    pub fn from_box(mut keyframes: Vec<Keyframe>, hitable: Arc<dyn Hitable>) -> AnimatedTransform {
+        assert!(!keyframes.is_empty(), "an animated transform needs at least one keyframe");
+        keyframes.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+        AnimatedTransform { keyframes, hitable }
+    }
+
+    /// The transform at the given time
+    pub fn keyframe(&self, time: f32) -> Keyframe {
+        let next = self.keyframes.iter().position(|keyframe| keyframe.time > time);
+
+        match next {
+            Some(0) => self.keyframes[0],
+            Some(next) => self.keyframes[next - 1].interpolate(&self.keyframes[next], time),
+            None => self.keyframes[self.keyframes.len() - 1],
+        }
+    }
+}
+
+impl Hitable for AnimatedTransform {
+    fn hit(&self, ray: &Ray, position_min: f32, position_max: f32) -> Option<HitRecord> {
+        let matrix = self.keyframe(ray.time).matrix();
+        let inverse = matrix.inverse();
+        hit_transformed(&*self.hitable,
+                        &matrix,
+                        &inverse,
+                        &inverse.transpose(),
+                        ray,
+                        position_min,
+                        position_max)
+    }
+
+    /// Bound the hitable over every transform it passes through between t0 and t1
+    ///
+    /// The corners of the bounding box of the hitable are moved by the
+    /// transforms at the keyframes and at evenly spaced times in between. A
+    /// corner strays from the closer of two neighbouring samples by less than
+    /// the distance between them, so the box is padded by the longest step.
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        let bbox = self.hitable.bounding_box(t0, t1)?;
+        let corners = corners(&bbox);
+
+        let steps = BOUNDING_BOX_SAMPLES * self.keyframes.len().max(1);
+        let mut times: Vec<f32> = (0..=steps).map(|i| t0 + (t1 - t0) * i as f32 / steps as f32)
+                                             .chain(self.keyframes
+                                                        .iter()
+                                                        .map(|keyframe| keyframe.time)
+                                                        .filter(|&time| time > t0 && time < t1))
+                                             .collect();
+        times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let mut minimum = Vec3::splat(f32::MAX);
+        let mut maximum = Vec3::splat(f32::MIN);
+        let mut previous: Option<[Vec3; 8]> = None;
+        let mut padding: f32 = 0.0;
+
+        for &time in &times {
+            let matrix = self.keyframe(time).matrix();
+            let mut moved = corners;
+            for corner in moved.iter_mut() {
+                *corner = matrix.transform_point3(*corner);
+                minimum = minimum.min(*corner);
+                maximum = maximum.max(*corner);
+            }
+            if let Some(previous) = previous {
+                for (a, b) in previous.iter().zip(moved.iter()) {
+                    padding = padding.max((*b - *a).length());
+                }
+            }
+            previous = Some(moved);
+        }
+
+        Some(AABB::from(minimum - Vec3::splat(padding), maximum + Vec3::splat(padding)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use materials::Empty;
+    use sphere::Sphere;
+
+    #[test]
+    fn test_transform_hits() {
+        let sphere: Arc<dyn Hitable> =
+            Arc::new(Sphere::new(Vec3::zero(), Vec3::zero(), 1.0, Empty::new(), 0.0, 1.0));
+
+        // an ellipsoid stretched along x, then turned to stand along y and moved away
+        let matrix = translation(Vec3::new(0.0, 0.0, -5.0))
+                     * rotation(Vec3::new(0.0, 0.0, 1.0), 90.0)
+                     * scaling(Vec3::new(2.0, 1.0, 1.0));
+        let ellipsoid = Transform::from_box(matrix, sphere.clone());
+
+        let bbox = ellipsoid.bounding_box(0.0, 1.0).unwrap();
+        assert!((bbox.minimum - Vec3::new(-1.0, -2.0, -6.0)).length() < 1e-4);
+        assert!((bbox.maximum - Vec3::new(1.0, 2.0, -4.0)).length() < 1e-4);
+
+        for &(origin, direction, parameter, normal) in
+            &[(Vec3::new(0.0, 5.0, -5.0), Vec3::new(0.0, -1.0, 0.0), 3.0, Vec3::new(0.0, 1.0, 0.0)),
+              (Vec3::new(5.0, 0.0, -5.0), Vec3::new(-1.0, 0.0, 0.0), 4.0, Vec3::new(1.0, 0.0, 0.0))]
+        {
+            let ray = Ray::new(origin, direction, 0.0);
+            let hit = ellipsoid.hit(&ray, 1e-4, f32::MAX).unwrap();
+            assert!((hit.parameter - parameter).abs() < 1e-4);
+            assert!((hit.point - ray.point_at_parameter(parameter)).length() < 1e-4);
+            assert!((hit.geometric_normal - normal).length() < 1e-4);
+            assert!((hit.shading_normal - normal).length() < 1e-4);
+            assert!(ellipsoid.hit(&ray, 1e-4, parameter - 0.1).is_none());
+        }
+
+        // the rotation about y matches Rotate, and Scale keeps parameters in world units
+        let ray = Ray::new(Vec3::new(3.0, 0.5, 0.5), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        let moved = Transform::from_box(rotation(Vec3::new(0.0, 1.0, 0.0), 30.0)
+                                        * translation(Vec3::new(0.0, 0.0, 1.0)),
+                                        sphere.clone());
+        let rotated = Rotate::from_box(30.0, Arc::new(Translate::from_box(Vec3::new(0.0, 0.0, 1.0),
+                                                                          sphere.clone())));
+        let (a, b) = (moved.hit(&ray, 1e-4, f32::MAX).unwrap(), rotated.hit(&ray, 1e-4, f32::MAX).unwrap());
+        assert!((a.parameter - b.parameter).abs() < 1e-4);
+        assert!((a.geometric_normal - b.geometric_normal).length() < 1e-4);
+
+        let scaled = Scale::from_box(2.0, sphere);
+        let hit = scaled.hit(&ray, 1e-4, f32::MAX).unwrap();
+        assert!((hit.parameter - (3.0 - 3.5f32.sqrt())).abs() < 1e-4);
+        assert!((hit.point - ray.point_at_parameter(hit.parameter)).length() < 1e-4);
+    }
+
+    #[test]
+    fn test_animated_transform() {
+        let sphere = Sphere::new(Vec3::zero(), Vec3::zero(), 1.0, Empty::new(), 0.0, 1.0);
+        let turn = Quat::from_rotation_z(f32::consts::FRAC_PI_2);
+        let keyframes = vec![Keyframe::new(1.0, Vec3::new(4.0, 0.0, 0.0), turn, Vec3::new(2.0, 1.0, 1.0)),
+                             Keyframe::new(0.0, Vec3::zero(), Quat::identity(), Vec3::new(2.0, 1.0, 1.0))];
+        let animated = AnimatedTransform::new(keyframes, sphere);
+
+        // halfway the stretched sphere has turned by 45 degrees and moved by 2
+        let halfway = animated.keyframe(0.5);
+        assert!((halfway.translation - Vec3::new(2.0, 0.0, 0.0)).length() < 1e-5);
+        let axis = halfway.rotation * Vec3::new(1.0, 0.0, 0.0);
+        assert!((axis - Vec3::new(0.5f32.sqrt(), 0.5f32.sqrt(), 0.0)).length() < 1e-5);
+
+        // rays at the start and the end of the frame see the sphere where it was then
+        let ray = Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        assert!((animated.hit(&ray, 1e-4, f32::MAX).unwrap().parameter - 4.0).abs() < 1e-4);
+        let ray = Ray::new(Vec3::new(4.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 1.0);
+        assert!((animated.hit(&ray, 1e-4, f32::MAX).unwrap().parameter - 3.0).abs() < 1e-4);
+
+        // the bounding box holds the sphere at every time in the interval
+        let bbox = animated.bounding_box(0.0, 1.0).unwrap();
+        for i in 0..=100 {
+            let keyframe = animated.keyframe(i as f32 / 100.0);
+            let moved = transform_box(&keyframe.matrix(), &AABB::from(Vec3::splat(-1.0), Vec3::splat(1.0)));
+            assert!(moved.minimum.cmpge(bbox.minimum).all() && moved.maximum.cmple(bbox.maximum).all());
+        }
+        let later = animated.bounding_box(1.0, 2.0).unwrap();
+        assert!(later.minimum.x() > 2.0);
+    }
+}
@@ -1,76 +1,310 @@
+use std::error::Error;
 use std::f32;
-use std::path::Path;
+use std::fmt;
 use std::sync::Arc;
 
-use glam::Vec3;
-use tobj;
+use glam::{Vec2, Vec3};
 
 use aabb::AABB;
+use bvh::BVH;
 use hitable::{HitRecord, Hitable};
 use materials::Material;
 use ray::Ray;
-use world::World;
+use texture::Texture;
 
-#[derive(Clone)]
-pub struct Triangle {
-    v0: Vec3,
-    v1: Vec3,
-    v2: Vec3,
-    n0: Vec3,
-    n1: Vec3,
-    n2: Vec3,
-    material: Arc<dyn Material>,
+/// MeshError describes why a mesh could not be loaded or built
+#[derive(Debug)]
+pub enum MeshError {
+    Load(String),
+    Invalid(String),
 }
 
-pub struct TriangleMesh {
-    triangles: Vec<Triangle>,
-    hitables: World,
-    material: Arc<dyn Material>,
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MeshError::Load(message) => write!(f, "could not load mesh: {}", message),
+            MeshError::Invalid(message) => write!(f, "invalid mesh: {}", message),
+        }
+    }
+}
+
+impl Error for MeshError {}
+
+/// Normals chooses how the normals of meshes without normals are generated
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Normals {
+    /// Average the normals of the faces around every vertex
+    Smooth,
+    /// Give every face its own geometric normal
+    Flat,
+}
+
+/// MeshData holds the vertex buffers of a mesh and the indices of its triangles
+///
+/// Every three indices select the vertices of a triangle. The normal, UV,
+/// tangent and color buffers are either empty or have an entry for every position.
+/// Triangles of a mesh without normals are shaded with their geometric normal.
+/// The material ids are either empty or select the material of every triangle.
+#[derive(Clone, Default)]
+pub struct MeshData {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<Vec2>,
+    pub tangents: Vec<Vec3>,
+    pub colors: Vec<Vec3>,
+    pub indices: Vec<u32>,
+    pub material_ids: Vec<u32>,
+}
+
+impl MeshData {
+    /// The number of triangles in the mesh
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// Check that the indices and the optional buffers match the positions
+    pub fn validate(&self) -> Result<(), MeshError> {
+        let vertices = self.positions.len();
+
+        if self.triangle_count() * 3 != self.indices.len() {
+            return Err(MeshError::Invalid(format!("{} indices do not form whole triangles",
+                                                  self.indices.len())));
+        }
+        if let Some(index) = self.indices.iter().find(|&&i| i as usize >= vertices) {
+            return Err(MeshError::Invalid(format!("index {} is out of range of {} vertices",
+                                                  index, vertices)));
+        }
+
+        for (name, length, expected) in &[("normals", self.normals.len(), vertices),
+                                          ("uvs", self.uvs.len(), vertices),
+                                          ("tangents", self.tangents.len(), vertices),
+                                          ("colors", self.colors.len(), vertices),
+                                          ("material ids", self.material_ids.len(), self.triangle_count())]
+        {
+            if *length != 0 && length != expected {
+                return Err(MeshError::Invalid(format!("{} {} where {} are needed",
+                                                      length, name, expected)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Append the triangles of another mesh after the triangles of this one
+    ///
+    /// The indices of the other mesh are offset past the vertices of this one.
+    /// Buffers that only one of the meshes has are padded with zeros, and
+    /// colors with white so that they leave the materials as they are.
+    pub fn append(&mut self, other: MeshData) {
+        let (vertices, triangles) = (self.positions.len(), self.triangle_count());
+        let (other_vertices, other_triangles) = (other.positions.len(), other.triangle_count());
+
+        append_buffer(&mut self.normals, vertices, other.normals, other_vertices, Vec3::zero());
+        append_buffer(&mut self.uvs, vertices, other.uvs, other_vertices, Vec2::zero());
+        append_buffer(&mut self.tangents, vertices, other.tangents, other_vertices, Vec3::zero());
+        append_buffer(&mut self.colors, vertices, other.colors, other_vertices, Vec3::one());
+        append_buffer(&mut self.material_ids, triangles, other.material_ids, other_triangles, 0);
+
+        let offset = vertices as u32;
+        self.indices.extend(other.indices.iter().map(|index| index + offset));
+        self.positions.extend(other.positions);
+    }
+
+    /// Generate normals for a mesh that has none
+    ///
+    /// Smooth normals are the area weighted average of the normals of the
+    /// faces around every vertex. Flat normals split the vertices between
+    /// their faces so that every face has its own normal. The data must be valid.
+    pub fn with_normals(self, normals: Normals) -> MeshData {
+        if !self.normals.is_empty() {
+            return self;
+        }
+
+        match normals {
+            Normals::Smooth => smooth_normals(self),
+            Normals::Flat => flat_normals(self),
+        }
+    }
+}
+
+fn append_buffer<T: Clone>(buffer: &mut Vec<T>, length: usize, other: Vec<T>, other_length: usize, fill: T) {
+    if buffer.is_empty() && other.is_empty() {
+        return;
+    }
+
+    buffer.resize(length, fill.clone());
+    if other.is_empty() {
+        buffer.resize(length + other_length, fill);
+    } else {
+        buffer.extend(other);
+    }
+}
+
+/// Give every vertex the sum of the normals of its faces weighted by their area
+fn smooth_normals(mut data: MeshData) -> MeshData {
+    let mut normals = vec![Vec3::zero(); data.positions.len()];
+
+    for triangle in data.indices.chunks(3) {
+        let (i, j, k) = (triangle[0] as usize, triangle[1] as usize, triangle[2] as usize);
+        let (v0, v1, v2) = (data.positions[i], data.positions[j], data.positions[k]);
+        let normal = (v1 - v0).cross(v2 - v0);
+        normals[i] += normal;
+        normals[j] += normal;
+        normals[k] += normal;
+    }
+
+    data.normals = normals.into_iter()
+                          .map(|normal| {
+                              if normal.length() > 0.0 {
+                                  normal.normalize()
+                              } else {
+                                  normal
+                              }
+                          })
+                          .collect();
+    data
+}
+
+/// Split the vertices between their faces so that every face has its own normal
+fn flat_normals(data: MeshData) -> MeshData {
+    let mut flat = MeshData { material_ids: data.material_ids.clone(),
+                              ..MeshData::default() };
+
+    for triangle in data.indices.chunks(3) {
+        let vertices = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+        let (v0, v1, v2) = (data.positions[vertices[0]],
+                            data.positions[vertices[1]],
+                            data.positions[vertices[2]]);
+        let normal = (v1 - v0).cross(v2 - v0);
+        let normal = if normal.length() > 0.0 { normal.normalize() } else { normal };
+
+        for &vertex in &vertices {
+            flat.indices.push(flat.positions.len() as u32);
+            flat.positions.push(data.positions[vertex]);
+            flat.normals.push(normal);
+            if !data.uvs.is_empty() {
+                flat.uvs.push(data.uvs[vertex]);
+            }
+            if !data.tangents.is_empty() {
+                flat.tangents.push(data.tangents[vertex]);
+            }
+            if !data.colors.is_empty() {
+                flat.colors.push(data.colors[vertex]);
+            }
+        }
+    }
+
+    flat
+}
+
+/// The vertex buffers and materials shared by the triangles of a mesh
+struct Mesh {
+    data: MeshData,
+    materials: Vec<Arc<dyn Material>>,
+    normal_maps: Vec<Option<Arc<dyn Texture>>>,
+}
+
+/// Triangle references its three vertices by index into the buffers of its mesh
+#[derive(Clone)]
+pub struct Triangle {
+    mesh: Arc<Mesh>,
+    index: usize,
 }
 
 impl Triangle {
-    /// Create a new triangle with vertices v0, v1, and v2
-    pub fn new<M: Material + 'static>(v0: Vec3,
-                                      v1: Vec3,
-                                      v2: Vec3,
-                                      n0: Vec3,
-                                      n1: Vec3,
-                                      n2: Vec3,
-                                      material: M)
-                                      -> Triangle {
-        let material = Arc::new(material);
-        Triangle { v0: v0,
-                   v1: v1,
-                   v2: v2,
-                   n0: n0,
-                   n1: n1,
-                   n2: n2,
-                   material: material }
-    }
-
-    pub fn from_box(v0: Vec3,
-                    v1: Vec3,
-                    v2: Vec3,
-                    n0: Vec3,
-                    n1: Vec3,
-                    n2: Vec3,
-                    material: Arc<dyn Material>)
-                    -> Triangle {
-        Triangle { v0: v0,
-                   v1: v1,
-                   v2: v2,
-                   n0: n0,
-                   n1: n1,
-                   n2: n2,
-                   material: material }
+    /// The indices of the three vertices of the triangle
+    fn vertices(&self) -> (usize, usize, usize) {
+        let indices = &self.mesh.data.indices[3 * self.index..3 * self.index + 3];
+        (indices[0] as usize, indices[1] as usize, indices[2] as usize)
+    }
+
+    fn material_id(&self) -> usize {
+        match self.mesh.data.material_ids.get(self.index) {
+            Some(&id) => id as usize,
+            None => 0,
+        }
+    }
+
+    /// The directions in which the u and v texture coordinates increase across the triangle
+    ///
+    /// None is returned for triangles without texture coordinates or whose
+    /// texture coordinates do not span an area.
+    fn uv_tangents(&self) -> Option<(Vec3, Vec3)> {
+        let uvs = &self.mesh.data.uvs;
+        if uvs.is_empty() {
+            return None;
+        }
+
+        let (i, j, k) = self.vertices();
+        let (v0, v1, v2) = self.positions();
+        let (duv1, duv2) = (uvs[j] - uvs[i], uvs[k] - uvs[i]);
+        let (edge1, edge2) = (v1 - v0, v2 - v0);
+
+        let area = duv1.x() * duv2.y() - duv1.y() * duv2.x();
+        if area.abs() < 1e-12 {
+            return None;
+        }
+        Some(((edge1 * duv2.y() - edge2 * duv1.y()) / area,
+              (edge2 * duv1.x() - edge1 * duv2.x()) / area))
+    }
+
+    /// The tangent at the barycentric coordinates u and v
+    ///
+    /// The interpolated tangents of the mesh are used when it has them,
+    /// and the direction of the u texture coordinate otherwise.
+    fn tangent(&self, u: f32, v: f32) -> Option<Vec3> {
+        let tangents = &self.mesh.data.tangents;
+        if tangents.is_empty() {
+            return self.uv_tangents().map(|(tangent, _)| tangent);
+        }
+
+        let (i, j, k) = self.vertices();
+        Some((1.0 - u - v) * tangents[i] + u * tangents[j] + v * tangents[k])
+    }
+
+    /// Bend the shading normal by the normal map of the material of the triangle
+    ///
+    /// The tangent frame follows the texture coordinates of the triangle, and
+    /// the interpolated tangents of the mesh when it has them. Triangles
+    /// without texture coordinates keep their normal.
+    fn map_normal(&self, normal: Vec3, tangent: Option<Vec3>, uv: Vec2, point: Vec3) -> Vec3 {
+        let normal_map = match &self.mesh.normal_maps[self.material_id()] {
+            Some(normal_map) => normal_map,
+            None => return normal,
+        };
+        let (tangent, bitangent) = match (tangent, self.uv_tangents()) {
+            (Some(tangent), Some((_, bitangent))) => (tangent, bitangent),
+            _ => return normal,
+        };
+
+        let tangent = (tangent - normal * normal.dot(tangent)).normalize();
+        let mut bitangent_direction = normal.cross(tangent);
+        if bitangent_direction.dot(bitangent) < 0.0 {
+            bitangent_direction = -bitangent_direction;
+        }
+
+        let mapped = 2.0 * normal_map.value(uv.x(), uv.y(), &point) - Vec3::one();
+        (mapped.x() * tangent + mapped.y() * bitangent_direction + mapped.z() * normal).normalize()
+    }
+
+    fn material(&self) -> &Arc<dyn Material> {
+        &self.mesh.materials[self.material_id()]
+    }
+
+    fn positions(&self) -> (Vec3, Vec3, Vec3) {
+        let (i, j, k) = self.vertices();
+        let positions = &self.mesh.data.positions;
+        (positions[i], positions[j], positions[k])
     }
 
     pub fn minimum(&self) -> Vec3 {
-        self.v0.min(self.v1.min(self.v2))
+        let (v0, v1, v2) = self.positions();
+        v0.min(v1.min(v2))
     }
 
     pub fn maximum(&self) -> Vec3 {
-        self.v0.max(self.v1.max(self.v2))
+        let (v0, v1, v2) = self.positions();
+        v0.max(v1.max(v2))
     }
 }
 
@@ -83,49 +317,85 @@ impl Hitable for Triangle {
     /// Journal of Graphics Tools Vol. 2 Issue 1, 1997
     /// http://www.acm.org/jgt/papers/MollerTrumbore97/
     ///
-    fn hit(&self, ray: &Ray, position_min: f32, _position_max: f32) -> Option<HitRecord> {
-        let edge1 = self.v1 - self.v0;
-        let edge2 = self.v2 - self.v0;
+    fn hit(&self, ray: &Ray, position_min: f32, position_max: f32) -> Option<HitRecord> {
+        let (v0, v1, v2) = self.positions();
+        let edge1 = v1 - v0;
+        let edge2 = v2 - v0;
 
         let pvec = ray.direction.cross(edge2);
         let determinant = edge1.dot(pvec);
 
-        if determinant < position_min {
+        // the ray is parallel to the triangle
+        if determinant.abs() < 1e-8 {
             return None;
         }
 
-        let tvec = ray.origin - self.v0;
-        let mut u = tvec.dot(pvec);
+        // dividing by the signed determinant hits both faces, so that rays refracted
+        // into a closed mesh leave it again; materials tell the faces apart by the
+        // geometric normal
+        let inverse_determinant = 1.0 / determinant;
+
+        let tvec = ray.origin - v0;
+        let u = tvec.dot(pvec) * inverse_determinant;
 
-        if u < 0.0 || u > determinant {
+        if !(0.0..=1.0).contains(&u) {
             return None;
         }
 
         let qvec = tvec.cross(edge1);
-        let mut v = ray.direction.dot(qvec);
+        let v = ray.direction.dot(qvec) * inverse_determinant;
 
-        if v < 0.0 || u + v > determinant {
+        if v < 0.0 || u + v > 1.0 {
             return None;
         }
 
-        let mut t = edge2.dot(qvec);
+        let t = edge2.dot(qvec) * inverse_determinant;
 
-        let inverse_determinant = 1.0 / determinant;
-        t *= inverse_determinant;
-        u *= inverse_determinant;
-        v *= inverse_determinant;
+        if t < position_min || t > position_max {
+            return None;
+        }
 
-        let point = u * self.v0 + v * self.v1 + (1.0 - u - v) * self.v2;
+        let point = ray.point_at_parameter(t);
         let geometric_normal = edge1.cross(edge2).normalize();
-        let shading_normal = ((1.0 - u - v) * self.n0 + u * self.n1 + v * self.n2).normalize();
 
-        Some(HitRecord::new(t,
-                            u,
-                            v,
-                            point,
-                            geometric_normal,
-                            shading_normal,
-                            self.material.clone()))
+        let normals = &self.mesh.data.normals;
+        let shading_normal = if normals.is_empty() {
+            geometric_normal
+        } else {
+            let (i, j, k) = self.vertices();
+            ((1.0 - u - v) * normals[i] + u * normals[j] + v * normals[k]).normalize()
+        };
+
+        // meshes without texture coordinates are textured by the barycentric coordinates
+        let uvs = &self.mesh.data.uvs;
+        let uv = if uvs.is_empty() {
+            Vec2::new(u, v)
+        } else {
+            let (i, j, k) = self.vertices();
+            (1.0 - u - v) * uvs[i] + u * uvs[j] + v * uvs[k]
+        };
+        let tangent = self.tangent(u, v);
+        let shading_normal = self.map_normal(shading_normal, tangent, uv, point);
+
+        let colors = &self.mesh.data.colors;
+        let vertex_color = if colors.is_empty() {
+            None
+        } else {
+            let (i, j, k) = self.vertices();
+            Some((1.0 - u - v) * colors[i] + u * colors[j] + v * colors[k])
+        };
+
+        let mut record = HitRecord::new(t,
+                                        uv.x(),
+                                        uv.y(),
+                                        point,
+                                        geometric_normal,
+                                        shading_normal,
+                                        self.material().clone());
+        record.barycentric = Some(Vec2::new(u, v));
+        record.tangent = tangent.filter(|tangent| tangent.length() > 0.0).map(|tangent| tangent.normalize());
+        record.vertex_color = vertex_color;
+        Some(record)
     }
 
     /// Create a bounding box around the triangle
@@ -137,67 +407,243 @@ impl Hitable for Triangle {
     }
 }
 
+/// TriangleMesh holds the triangles of a mesh in their own BVH
+///
+/// Clones of a mesh share the same BVH, so a mesh can be placed many times
+/// in a scene, each time wrapped in its own transformation, while its
+/// triangles are stored only once.
+#[derive(Clone)]
+pub struct TriangleMesh {
+    bvh: Arc<BVH>,
+}
+
 impl TriangleMesh {
-    pub fn new(triangles: Vec<Triangle>, material: Arc<dyn Material>) -> TriangleMesh {
-        let mut world = World::new();
+    /// Create a mesh from its vertex buffers with a single material
+    pub fn new(data: MeshData, material: Arc<dyn Material>) -> Result<TriangleMesh, MeshError> {
+        TriangleMesh::with_materials(data, vec![material])
+    }
+
+    /// Create a mesh whose triangles select their material by the material ids of the data
+    ///
+    /// Triangles use the first material when the data has no material ids.
+    pub fn with_materials(data: MeshData,
+                          materials: Vec<Arc<dyn Material>>)
+                          -> Result<TriangleMesh, MeshError> {
+        let normal_maps = vec![None; materials.len()];
+        TriangleMesh::with_normal_maps(data, materials, normal_maps)
+    }
 
-        for triangle in &triangles {
-            world.add(triangle.clone());
+    /// Create a mesh whose materials may each have a normal map
+    ///
+    /// The normal maps are indexed like the materials and store tangent
+    /// space normals in their colors.
+    pub fn with_normal_maps(data: MeshData,
+                            materials: Vec<Arc<dyn Material>>,
+                            normal_maps: Vec<Option<Arc<dyn Texture>>>)
+                            -> Result<TriangleMesh, MeshError> {
+        data.validate()?;
+
+        if materials.is_empty() {
+            return Err(MeshError::Invalid(String::from("the mesh has no material")));
+        }
+        if normal_maps.len() != materials.len() {
+            return Err(MeshError::Invalid(format!("{} normal maps for {} materials",
+                                                  normal_maps.len(),
+                                                  materials.len())));
+        }
+        if let Some(id) = data.material_ids.iter().find(|&&id| id as usize >= materials.len()) {
+            return Err(MeshError::Invalid(format!("material id {} is out of range of {} materials",
+                                                  id,
+                                                  materials.len())));
         }
 
-        TriangleMesh { triangles: triangles,
-                       hitables: world,
-                       material: material }
-    }
+        let triangle_count = data.triangle_count();
+        let mesh = Arc::new(Mesh { data,
+                                   materials,
+                                   normal_maps });
 
-    pub fn from(filepath: &str, material: Arc<dyn Material>) -> TriangleMesh {
-        let obj = tobj::load_obj(&Path::new(&filepath));
-        let (models, _) = obj.unwrap();
+        let mut hitables: Vec<Arc<dyn Hitable>> =
+            (0..triangle_count).map(|index| {
+                                   Arc::new(Triangle { mesh: mesh.clone(),
+                                                       index }) as Arc<dyn Hitable>
+                               })
+                               .collect();
 
-        let mut triangles: Vec<Triangle> = Vec::new();
-        for model in models {
-            let mesh = &model.mesh;
+        Ok(TriangleMesh { bvh: Arc::new(BVH::new(&mut hitables, 0.0, 1.0)) })
+    }
+}
 
-            let positions: Vec<Vec3> = mesh.positions
-                                           .chunks(3)
-                                           .map(|i| Vec3::new(i[0], i[1], i[2]))
-                                           .collect();
+impl Hitable for TriangleMesh {
+    fn hit(&self, ray: &Ray, position_min: f32, position_max: f32) -> Option<HitRecord> {
+        self.bvh.hit(ray, position_min, position_max)
+    }
 
-            let normals: Vec<Vec3> = mesh.normals
-                                         .chunks(3)
-                                         .map(|i| Vec3::new(i[0], i[1], i[2]))
-                                         .collect();
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        self.bvh.bounding_box(t0, t1)
+    }
+}
 
-            for i in 0..mesh.indices.len() / 3 {
-                let (i, j, k) =
-                    (mesh.indices[3 * i], mesh.indices[3 * i + 1], mesh.indices[3 * i + 2]);
-                let (v0, v1, v2) =
-                    (positions[i as usize], positions[j as usize], positions[k as usize]);
-                let (n0, n1, n2) = (normals[i as usize], normals[j as usize], normals[k as usize]);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use materials::{Diffuse, Empty, Refractive, RoughDielectric};
+    use pdf::PDF;
+    use sampler::{IndependentSampler, Sampler};
+    use texture::ConstantTexture;
+    use transformations::Translate;
+
+    /// A closed cube from the origin to the edge with its faces wound outwards
+    fn cube(edge: f32) -> MeshData {
+        let positions = (0..8).map(|i| {
+                                  edge * Vec3::new((i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32)
+                              })
+                              .collect();
+        let faces = [[0, 2, 3, 1], [4, 5, 7, 6], [0, 1, 5, 4], [2, 6, 7, 3], [0, 4, 6, 2], [1, 3, 7, 5]];
+        let indices = faces.iter()
+                           .flat_map(|f| vec![f[0], f[1], f[2], f[0], f[2], f[3]])
+                           .collect();
+        MeshData { positions,
+                   indices,
+                   ..MeshData::default() }
+    }
 
-                let triangle = Triangle::from_box(v0, v1, v2, n0, n1, n2, material.clone());
-                triangles.push(triangle);
-            }
+    #[test]
+    fn test_mesh_instances() {
+        let data = MeshData { positions: vec![Vec3::new(0.0, 0.0, 0.0),
+                                              Vec3::new(1.0, 0.0, 0.0),
+                                              Vec3::new(1.0, 1.0, 0.0),
+                                              Vec3::new(0.0, 1.0, 0.0)],
+                              normals: vec![Vec3::new(0.0, 0.0, 1.0); 4],
+                              uvs: vec![Vec2::new(0.0, 0.0),
+                                        Vec2::new(2.0, 0.0),
+                                        Vec2::new(2.0, 2.0),
+                                        Vec2::new(0.0, 2.0)],
+                              indices: vec![0, 1, 2, 0, 2, 3],
+                              ..MeshData::default() };
+        assert!(data.validate().is_ok());
+        assert!(MeshData { indices: vec![0, 1, 4], ..data.clone() }.validate().is_err());
+        assert!(MeshData { uvs: vec![Vec2::new(0.0, 0.0)], ..data.clone() }.validate().is_err());
+
+        // both instances share the triangles of the square
+        let square: Arc<dyn Hitable> =
+            Arc::new(TriangleMesh::new(data, Arc::new(Empty::new())).unwrap());
+        let mut objects: Vec<Arc<dyn Hitable>> =
+            vec![Arc::new(Translate::from_box(Vec3::new(-2.0, 0.0, 0.0), square.clone())),
+                 Arc::new(Translate::from_box(Vec3::new(2.0, 0.0, -1.0), square.clone()))];
+        let world = BVH::new(&mut objects, 0.0, 1.0);
+
+        for &(x, distance) in &[(-1.5, 5.0), (2.5, 6.0)] {
+            let ray = Ray::new(Vec3::new(x, 0.25, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+            let hit = world.hit(&ray, 1e-4, f32::MAX).unwrap();
+            assert!((hit.parameter - distance).abs() < 1e-5);
+            assert!((hit.point.z() - (5.0 - distance)).abs() < 1e-5);
+            assert_eq!(hit.shading_normal, Vec3::new(0.0, 0.0, 1.0));
+            assert!((hit.tangent.unwrap() - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-5);
+
+            // textures follow the texture coordinates rather than the barycentrics
+            assert!((hit.u - 1.0).abs() < 1e-5 && (hit.v - 0.5).abs() < 1e-5);
+            let barycentric = hit.barycentric.unwrap();
+            assert!((barycentric - Vec2::new(0.25, 0.25)).length() < 1e-5);
         }
 
-        TriangleMesh::new(triangles, material)
+        let miss = Ray::new(Vec3::new(0.5, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(world.hit(&miss, 1e-4, f32::MAX).is_none());
     }
-}
 
-impl Hitable for TriangleMesh {
-    fn hit(&self, ray: &Ray, position_min: f32, position_max: f32) -> Option<HitRecord> {
-        self.hitables.hit(&ray, position_min, position_max)
+    #[test]
+    fn test_vertex_colors() {
+        let triangle = MeshData { positions: vec![Vec3::new(0.0, 0.0, 0.0),
+                                                  Vec3::new(1.0, 0.0, 0.0),
+                                                  Vec3::new(0.0, 1.0, 0.0)],
+                                  indices: vec![0, 1, 2],
+                                  ..MeshData::default() };
+        let mut data = MeshData { colors: vec![Vec3::new(1.0, 0.0, 0.0),
+                                               Vec3::new(0.0, 1.0, 0.0),
+                                               Vec3::new(0.0, 0.0, 1.0)],
+                                  ..triangle.clone() };
+
+        // a mesh without colors appended to one with colors is left white
+        let offset = Vec3::new(2.0, 0.0, 0.0);
+        data.append(MeshData { positions: triangle.positions.iter().map(|&p| p - offset).collect(),
+                               ..triangle });
+        assert_eq!(data.colors[5], Vec3::one());
+
+        let material = Arc::new(Diffuse::new(ConstantTexture::new(0.5, 0.5, 0.5), 0.0));
+        let mesh = TriangleMesh::new(data, material).unwrap();
+
+        // the colors of the vertices are blended by the barycentric coordinates of the hit
+        let ray = Ray::new(Vec3::new(0.5, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = mesh.hit(&ray, 1e-4, f32::MAX).unwrap();
+        assert!((hit.vertex_color.unwrap() - Vec3::new(0.25, 0.5, 0.25)).length() < 1e-5);
+        let albedo = hit.material.albedo(&ray, &hit).unwrap();
+        assert!((albedo - Vec3::new(0.125, 0.25, 0.125)).length() < 1e-5);
+
+        let ray = Ray::new(Vec3::new(-1.5, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = mesh.hit(&ray, 1e-4, f32::MAX).unwrap();
+        assert_eq!(hit.material.albedo(&ray, &hit), Some(Vec3::splat(0.5)));
     }
 
-    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
-        let mut minimum = Vec3::splat(f32::MAX);
-        let mut maximum = Vec3::splat(f32::MIN);
+    #[test]
+    fn test_absorption_through_closed_mesh() {
+        let (edge, sigma) = (2.0, Vec3::new(0.1, 0.5, 1.0));
+        let glass = Arc::new(Refractive::new(1.0).with_absorption(sigma));
+        let mesh = TriangleMesh::new(cube(edge), glass).unwrap();
+        let mut sampler = IndependentSampler::new(1, 0);
+
+        // the ray enters through the front face without being absorbed
+        let ray = Ray::new(Vec3::new(0.6, 1.4, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = mesh.hit(&ray, 1e-4, f32::MAX).unwrap();
+        assert_eq!(hit.geometric_normal, Vec3::new(0.0, 0.0, 1.0));
+        let entered = hit.material.scatter(&ray, &hit, &mut sampler).unwrap();
+        assert_eq!(entered.attenuation, Vec3::one());
+
+        // and hits the back face from inside, having crossed the cube
+        let inside = entered.specular_ray;
+        let hit = mesh.hit(&inside, 1e-4, f32::MAX).unwrap();
+        assert_eq!(hit.geometric_normal, Vec3::new(0.0, 0.0, -1.0));
+        assert!((hit.parameter - edge).abs() < 1e-4);
+        let left = hit.material.scatter(&inside, &hit, &mut sampler).unwrap();
+        let expected = Vec3::new((-sigma.x() * edge).exp(), (-sigma.y() * edge).exp(), (-sigma.z() * edge).exp());
+        assert!((left.attenuation - expected).length() < 1e-4);
+
+        assert!(mesh.hit(&left.specular_ray, 1e-4, f32::MAX).is_none());
+    }
 
-        for triangle in &self.triangles {
-            minimum = minimum.min(triangle.minimum());
-            maximum = maximum.max(triangle.maximum());
+    #[test]
+    fn test_rough_dielectric_from_inside_mesh() {
+        let glass = Arc::new(RoughDielectric::new(1.5, 0.3, ConstantTexture::new(1.0, 1.0, 1.0)));
+        let mesh = TriangleMesh::new(cube(2.0), glass).unwrap();
+        let mut sampler = IndependentSampler::new(1, 0);
+
+        // a ray from the center of the cube leaves through the face at x = 2
+        let ray = Ray::new(Vec3::new(1.0, 1.0, 1.0), Vec3::new(1.0, 0.3, 0.2), 0.0);
+        let hit = mesh.hit(&ray, 1e-4, f32::MAX).unwrap();
+        assert_eq!(hit.geometric_normal, Vec3::new(1.0, 0.0, 0.0));
+
+        let (mut refracted, mut reflected) = (0, 0);
+        for i in 0..1000 {
+            sampler.start_pixel_sample(0, 0, i);
+            let scatter = hit.material.scatter(&ray, &hit, &mut sampler).unwrap();
+            match scatter.pdf {
+                PDF::DielectricPDF { eta, .. } => assert!((eta - 1.0 / 1.5).abs() < 1e-6),
+                _ => panic!("rough glass is not sampled by its microfacets"),
+            }
+
+            let direction = match scatter.pdf.generate(&mut sampler) {
+                Some(direction) => direction,
+                None => continue,
+            };
+            let scattered = Ray::new(hit.point, direction, 0.0);
+            if hit.material.scattering(&ray, &hit, &scattered).x() > 0.0 {
+                if direction.x() > 0.0 {
+                    refracted += 1;
+                } else {
+                    reflected += 1;
+                }
+            }
         }
 
-        Some(AABB::from(minimum, maximum))
+        // light leaves the glass and is reflected back inside
+        assert!(refracted > 0 && reflected > 0);
     }
 }
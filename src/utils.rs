@@ -35,6 +35,11 @@ pub fn gamma_correct(luminance: f32, gamma: f32) -> f32 {
     luminance.powf(1.0 / gamma)
 }
 
+/// Compute the relative luminance of a linear RGB color
+pub fn luminance(color: &Vec3) -> f32 {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
+}
+
 /// Check if a computed color contains any NaNs
 pub fn de_nan(color: &Vec3) -> Vec3 {
     let mut correction = Vec3::new(color.x(), color.y(), color.z());
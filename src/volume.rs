@@ -7,6 +7,7 @@ use aabb::AABB;
 use hitable::{HitRecord, Hitable};
 use materials::{Isotropic, Material};
 use ray::Ray;
+use sampler::hash_float;
 use texture::Texture;
 
 pub struct Volume {
@@ -26,6 +27,13 @@ impl Volume {
                  boundary,
                  material }
     }
+
+    pub fn from_box(density: f32, boundary: Arc<dyn Hitable>, texture: Arc<dyn Texture>) -> Volume {
+        let material = Arc::new(Isotropic::from_box(texture));
+        Volume { density,
+                 boundary,
+                 material }
+    }
 }
 
 impl Hitable for Volume {
@@ -41,7 +49,17 @@ impl Hitable for Volume {
                 if hit1.parameter < hit2.parameter {
                     let distance_inside_boundary =
                         (hit2.parameter - hit1.parameter) * ray.direction.length();
-                    let hit_distance = -(1.0 / self.density) * rand::random::<f32>().ln();
+                    // rays traced without a sample decide the distance themselves so that it is reproducible
+                    let random = ray.medium_sample.unwrap_or_else(|| {
+                        hash_float(&[u64::from(ray.origin.x().to_bits()),
+                                     u64::from(ray.origin.y().to_bits()),
+                                     u64::from(ray.origin.z().to_bits()),
+                                     u64::from(ray.direction.x().to_bits()),
+                                     u64::from(ray.direction.y().to_bits()),
+                                     u64::from(ray.direction.z().to_bits()),
+                                     u64::from(ray.time.to_bits())])
+                    });
+                    let hit_distance = -(1.0 / self.density) * (1.0 - random).ln();
 
                     if hit_distance < distance_inside_boundary {
                         let t = hit1.parameter + hit_distance / ray.direction.length();
@@ -65,3 +83,27 @@ impl Hitable for Volume {
         self.boundary.bounding_box(t0, t1)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use materials::Empty;
+    use sphere::Sphere;
+    use texture::ConstantTexture;
+    use transformations::Translate;
+
+    #[test]
+    fn test_medium_sample_picks_distance() {
+        let boundary = Sphere::new(Vec3::zero(), Vec3::zero(), 1.0, Empty::new(), 0.0, 1.0);
+        let fog = Translate::new(Vec3::new(0.0, 0.0, -5.0),
+                                 Volume::new(1.0, boundary, ConstantTexture::new(1.0, 1.0, 1.0)));
+        let ray = |sample: f32| Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), 0.0).with_medium_sample(sample);
+
+        // the free flight of half the rays is ln 2, and the sample survives the transform
+        let hit = fog.hit(&ray(0.5), 1e-4, f32::MAX).unwrap();
+        assert!((hit.parameter - (4.0 + 2f32.ln())).abs() < 1e-4);
+
+        // rays whose free flight is longer than the fog pass through it
+        assert!(fog.hit(&ray(0.99), 1e-4, f32::MAX).is_none());
+    }
+}
@@ -0,0 +1,104 @@
+//! Render every example scene and compare it against its stored reference image
+//!
+//! The scenes are rendered small, with few samples and a fixed seed, so any
+//! change to the images comes from a change to the renderer. Each scene that
+//! drifted from its reference is reported together with its error, and its
+//! render is written to a temporary directory for inspection.
+//!
+//! After an intended change, the references are written again with:
+//!
+//! ```text
+//! RENDERAMA_UPDATE_REFERENCES=1 cargo test --test reference
+//! ```
+
+extern crate renderama;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use renderama::metrics;
+use renderama::output::OutputFormat;
+use renderama::{render, scene, Film, Settings};
+
+const WIDTH: usize = 32;
+const HEIGHT: usize = 24;
+const SAMPLES: u32 = 4;
+const BOUNCES: u32 = 4;
+const SEED: u64 = 0;
+
+/// The largest relative mean squared error allowed before a scene has drifted
+///
+/// Identical renders differ only by the precision of the HDR format.
+const TOLERANCE: f32 = 1e-3;
+
+fn reference_path(name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "references", &format!("{}.hdr", name)].iter()
+                                                                                .collect()
+}
+
+#[test]
+fn test_reference_images() {
+    let update = env::var_os("RENDERAMA_UPDATE_REFERENCES").is_some();
+    let output_directory = env::temp_dir().join("renderama-references");
+
+    let mut settings = Settings::new(WIDTH, HEIGHT, SAMPLES, BOUNCES);
+    settings.seed = SEED;
+
+    let mut report = vec![format!("{:<16} {:>10} {:>10}", "scene", "rmse", "relmse")];
+    let mut drifted = Vec::new();
+
+    for name in scene::SCENES.iter() {
+        let scene = scene::from_name(name, WIDTH, HEIGHT, SEED).unwrap();
+        let film = render(&scene, &settings);
+        let path = reference_path(name);
+        let path = path.to_str().unwrap();
+
+        if update {
+            film.write(path, OutputFormat::HDR).unwrap();
+            report.push(format!("{:<16} updated", name));
+            continue;
+        }
+
+        let reference = match Film::read(path) {
+            Ok(reference) => reference,
+            Err(error) => {
+                report.push(format!("{:<16} missing reference: {}", name, error));
+                drifted.push(*name);
+                continue;
+            }
+        };
+
+        if (reference.width, reference.height) != (film.width, film.height) {
+            report.push(format!("{:<16} reference is {} x {}",
+                                name, reference.width, reference.height));
+            drifted.push(*name);
+            continue;
+        }
+
+        let rmse = metrics::rmse(&film.pixels, &reference.pixels);
+        let relative_mse = metrics::relative_mse(&film.pixels, &reference.pixels);
+        let passed = relative_mse <= TOLERANCE;
+
+        report.push(format!("{:<16} {:>10.6} {:>10.6}{}",
+                            name,
+                            rmse,
+                            relative_mse,
+                            if passed { "" } else { "  drifted" }));
+
+        if !passed {
+            fs::create_dir_all(&output_directory).unwrap();
+            let output = output_directory.join(format!("{}.hdr", name));
+            film.write(output.to_str().unwrap(), OutputFormat::HDR).unwrap();
+            drifted.push(*name);
+        }
+    }
+
+    println!("{}", report.join("\n"));
+
+    assert!(drifted.is_empty(),
+            "{} drifted from their reference images, renders written to {}\n{}",
+            drifted.join(", "),
+            output_directory.display(),
+            report.join("\n"));
+}